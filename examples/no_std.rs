@@ -1,12 +1,12 @@
-///! This example uses the library as you would in a `no_std` environment, but we are of course
-///! using `std`.
-///!
-///! This doesn't do actual HTTP requests, instead it does something fake to show how you might use
-///! it to hook stuff up to your own sockets
+//! This example uses the library as you would in a `no_std` environment, but we are of course
+//! using `std`.
+//!
+//! This doesn't do actual HTTP requests, instead it does something fake to show how you might use
+//! it to hook stuff up to your own sockets
 use http_io::error::Result;
 
 #[allow(dead_code)]
-const CANNED_RESPONSE: &'static [u8] = b"\
+const CANNED_RESPONSE: &[u8] = b"\
 HTTP/1.1 200 OK\r\n\
 Content-Length: 11\r\n\
 Content-Type: text/html\r\n\
@@ -1,8 +1,10 @@
-use std::io;
+use std::io::{self, Seek as _};
 use std::net;
 use std::path::PathBuf;
 
-use http_io::protocol::{HttpBody, HttpResponse, HttpStatus};
+use http_io::protocol::{
+    etag_matches, parse_range, weak_etag, HttpBody, HttpHeaders, HttpResponse, HttpStatus,
+};
 use http_io::server::{HttpRequestHandler, HttpServer};
 
 struct FileHandler {
@@ -37,7 +39,11 @@ impl From<Error> for HttpResponse<Box<dyn io::Read>> {
 impl<I: io::Read> HttpRequestHandler<I> for FileHandler {
     type Error = Error;
 
-    fn get(&mut self, uri: String) -> Result<HttpResponse<Box<dyn io::Read>>> {
+    fn get(
+        &mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read>>> {
         let path = self.file_root.join(uri.trim_start_matches("/"));
         println!("Request for {:?}", path);
         let attrs = std::fs::metadata(&path)?;
@@ -66,29 +72,69 @@ impl<I: io::Read> HttpRequestHandler<I> for FileHandler {
             "#,
                 uri, &file_list
             );
-            Ok(HttpResponse::new(
+            return Ok(HttpResponse::new(
                 HttpStatus::OK,
                 Box::new(io::Cursor::new(page)),
-            ))
+            ));
+        }
+
+        let etag = weak_etag(attrs.len(), attrs.modified()?);
+        let mut res = if headers
+            .get("If-None-Match")
+            .is_some_and(|if_none_match| etag_matches(if_none_match, &etag))
+        {
+            HttpResponse::new(
+                HttpStatus::NotModified,
+                Box::new(io::empty()) as Box<dyn io::Read>,
+            )
+        } else if let Some(range) = headers.get("Range") {
+            let mut res = match parse_range(range, attrs.len()) {
+                Some((start, len)) => {
+                    let mut file = std::fs::File::open(path)?;
+                    file.seek(io::SeekFrom::Start(start))?;
+                    let mut res = HttpResponse::from_reader(
+                        HttpStatus::PartialContent,
+                        len,
+                        Box::new(file) as Box<dyn io::Read>,
+                    );
+                    let end = start + len - 1;
+                    let content_range = format!("bytes {}-{}/{}", start, end, attrs.len());
+                    res.add_header("Content-Range", content_range);
+                    res
+                }
+                None => {
+                    let mut res =
+                        HttpResponse::from_string(HttpStatus::RequestedRangeNotSatisfiable, "");
+                    res.add_header("Content-Range", format!("bytes */{}", attrs.len()));
+                    res.add_header("Content-Length", "0");
+                    res
+                }
+            };
+            res.add_header("Accept-Ranges", "bytes");
+            res
         } else {
-            let mut res = HttpResponse::new(
+            let mut res = HttpResponse::from_reader(
                 HttpStatus::OK,
+                attrs.len(),
                 Box::new(std::fs::File::open(path)?) as Box<dyn io::Read>,
             );
-            res.add_header("Content-Length", attrs.len().to_string());
-            Ok(res)
-        }
+            res.add_header("Accept-Ranges", "bytes");
+            res
+        };
+        res.add_header("ETag", etag);
+        Ok(res)
     }
 
     fn put(
         &mut self,
         uri: String,
-        mut stream: HttpBody<&mut I>,
+        _headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
     ) -> Result<HttpResponse<Box<dyn io::Read>>> {
         let path = self.file_root.join(uri.trim_start_matches("/"));
         println!("Uploading to {:?}", path);
         let mut file = std::fs::File::create(path)?;
-        io::copy(&mut stream, &mut file)?;
+        io::copy(stream, &mut file)?;
         Ok(HttpResponse::new(HttpStatus::OK, Box::new(io::empty())))
     }
 }
@@ -97,6 +143,7 @@ fn main() -> Result<()> {
     let handler = FileHandler::new(std::env::current_dir()?);
     let socket = net::TcpListener::bind("127.0.0.1:8080")?;
     let mut server = HttpServer::new(socket, handler);
+    server.set_error_callback(|e| println!("Error {:?}", e));
     println!("Server started on port 8080");
     server.serve_forever();
 }
@@ -0,0 +1,99 @@
+//! Minimal base64 encoding, so it is available without std or extra dependencies.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode the given bytes using the standard alphabet with padding (RFC 4648 section 4).
+pub(crate) fn encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Decode base64 in the standard alphabet, with or without padding. Returns `None` if the input
+/// isn't valid base64.
+pub(crate) fn decode(input: &str) -> Option<Vec<u8>> {
+    let padded = input.as_bytes();
+    let input = padded
+        .strip_suffix(b"==")
+        .or_else(|| padded.strip_suffix(b"="))
+        .unwrap_or(padded);
+    if (input.len() != padded.len() && !padded.len().is_multiple_of(4)) || input.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 4 * 3 + 2);
+    for chunk in input.chunks(4) {
+        let mut n = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|a| a == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            output.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod base64_tests {
+    use super::{decode, encode};
+
+    #[test]
+    fn encode_padding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn encode_high_bytes() {
+        assert_eq!(encode(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        for input in [
+            &b""[..],
+            b"f",
+            b"fo",
+            b"foo",
+            b"foob",
+            b"fooba",
+            b"foobar",
+            b"\xff\xfe",
+        ] {
+            assert_eq!(decode(&encode(input)).unwrap(), input);
+        }
+        assert_eq!(decode("Zm9vYg").unwrap(), b"foob");
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode("Zm9v!"), None);
+        assert_eq!(decode("Zm9vY"), None);
+        assert_eq!(decode("Zg="), None);
+        assert_eq!(decode("="), None);
+        assert_eq!(decode("Zm=v"), None);
+    }
+}
@@ -26,7 +26,7 @@
 //!
 //! fn main() -> Result<()> {
 //!     let http_url: HttpUrl = "http://www.google.com".parse()?;
-//!     let s = TcpStream::connect((http_url.host(), http_url.port()))?;
+//!     let s = TcpStream::connect((http_url.socket_host(), http_url.port()))?;
 //!     let mut response = HttpRequestBuilder::get(http_url)?.send(s)?.finish()?;
 //!     println!("{:#?}", response.headers);
 //!     io::copy(&mut response.body, &mut io::stdout())?;
@@ -37,15 +37,14 @@
 //! ```rust
 //! use http_io::client::HttpClient;
 //! use http_io::error::Result;
-//! use http_io::url::Url;
+//! use http_io::url::HttpUrl;
 //! use std::io;
 //!
 //! fn main() -> Result<()> {
-//!     let url = Url::parse("http://www.google.com")?;
+//!     let url: HttpUrl = "http://www.google.com".parse()?;
 //!     let mut client = HttpClient::<std::net::TcpStream>::new();
 //!     for path in &["/", "/favicon.ico", "/robots.txt"] {
-//!         let mut url = url.clone();
-//!         url.set_path(&path);
+//!         let url = url.clone().with_path(path)?;
 //!         io::copy(&mut client.get(url)?.finish()?.body, &mut io::stdout())?;
 //!     }
 //!     Ok(())
@@ -55,21 +54,39 @@
 use crate::error::{Error, Result};
 use crate::io;
 #[cfg(feature = "std")]
-use crate::protocol::{HttpBody, HttpStatus};
-use crate::protocol::{HttpMethod, HttpRequest, OutgoingRequest};
+use crate::protocol::HttpBody;
+use crate::protocol::{
+    HttpHeaders, HttpMethod, HttpRequest, HttpResponse, HttpStatus, OutgoingRequest,
+};
 #[cfg(feature = "std")]
 use crate::url::Scheme;
-use crate::url::{HttpUrl, Url};
+use crate::url::{encode_component, HttpUrl, Url};
 #[cfg(not(feature = "std"))]
-use alloc::string::{String, ToString as _};
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
 use core::convert::TryInto;
 use core::fmt::Display;
 use core::hash::Hash;
-use hashbrown::HashMap;
+use core::time::Duration;
+use hashbrown::{HashMap, HashSet};
 
 /// A struct for building up an HTTP request.
 pub struct HttpRequestBuilder {
+    url: Url,
     request: HttpRequest<io::Empty>,
+    body: Option<Box<dyn io::Read>>,
+}
+
+/// The path and query of the url, which is what goes in the request line.
+fn request_target(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().into(),
+    }
 }
 
 impl HttpRequestBuilder {
@@ -105,6 +122,26 @@ impl HttpRequestBuilder {
         HttpRequestBuilder::new(url, HttpMethod::Options)
     }
 
+    /// Create a `HttpRequestBuilder` to build an OPTIONS request about the server as a whole
+    /// rather than any one resource, which is sent with the request target `*`. Only the host and
+    /// port of the url are used.
+    pub fn options_server<U: TryInto<HttpUrl>>(url: U) -> Result<Self>
+    where
+        <U as TryInto<HttpUrl>>::Error: Display,
+    {
+        let mut builder = HttpRequestBuilder::new(url, HttpMethod::Options)?;
+        builder.request.uri = "*".into();
+        Ok(builder)
+    }
+
+    /// Create a `HttpRequestBuilder` to build a PATCH request
+    pub fn patch<U: TryInto<HttpUrl>>(url: U) -> Result<Self>
+    where
+        <U as TryInto<HttpUrl>>::Error: Display,
+    {
+        HttpRequestBuilder::new(url, HttpMethod::Patch)
+    }
+
     /// Create a `HttpRequestBuilder` to build a POST request
     pub fn post<U: TryInto<HttpUrl>>(url: U) -> Result<Self>
     where
@@ -121,6 +158,16 @@ impl HttpRequestBuilder {
         HttpRequestBuilder::new(url, HttpMethod::Put)
     }
 
+    /// Create a `HttpRequestBuilder` to build a TRACE request, which a server willing to answer
+    /// echoes back as it received it. Don't add headers holding credentials to it, a server or
+    /// proxy along the way could reflect them back.
+    pub fn trace<U: TryInto<HttpUrl>>(url: U) -> Result<Self>
+    where
+        <U as TryInto<HttpUrl>>::Error: Display,
+    {
+        HttpRequestBuilder::new(url, HttpMethod::Trace)
+    }
+
     /// Create a `HttpRequestBuilder`. May fail if the given url does not parse.
     pub fn new<U: TryInto<HttpUrl>>(url: U, method: HttpMethod) -> Result<Self>
     where
@@ -129,19 +176,139 @@ impl HttpRequestBuilder {
         let url: HttpUrl = url
             .try_into()
             .map_err(|e| Error::ParseError(e.to_string()))?;
-        let mut request = HttpRequest::new(method, url.url().path());
-        request.add_header("Host", url.host().to_string());
+        let mut request = HttpRequest::new(method, request_target(url.url()));
+        request.add_header("Host", url.authority());
         request.add_header("User-Agent", "http_io");
         request.add_header("Accept", "*/*");
         if method.has_body() {
             request.add_header("Transfer-Encoding", "chunked");
         }
-        Ok(HttpRequestBuilder { request })
+        Ok(HttpRequestBuilder {
+            url: url.url().clone(),
+            request,
+            body: None,
+        })
     }
 
-    /// Send the built request on the given socket
+    /// Send the built request on the given socket. If a body was given to the builder it is
+    /// written as well, but the request still needs to be finished.
+    ///
+    /// With `Expect: 100-continue`, the server is given the chance to turn the request down before
+    /// the body is sent, see `HttpRequest::serialize`.
     pub fn send<S: io::Read + io::Write>(self, socket: S) -> Result<OutgoingRequest<S>> {
-        self.request.serialize(io::BufWriter::new(socket))
+        self.send_with_continue_wait(socket, |_, _| Ok(()))
+    }
+
+    /// Like `send`, see `HttpRequest::serialize_with_continue_wait`.
+    fn send_with_continue_wait<S: io::Read + io::Write>(
+        self,
+        socket: S,
+        wait: impl FnMut(&S, bool) -> Result<()>,
+    ) -> Result<OutgoingRequest<S>> {
+        let mut request = self
+            .request
+            .serialize_with_continue_wait(io::BufWriter::new(socket), wait)?;
+        if let Some(mut body) = self.body {
+            io::copy(&mut body, &mut request)?;
+        }
+        Ok(request)
+    }
+
+    /// Send the request with the given HTTP version instead of 1.1. Chunked encoding isn't
+    /// available before 1.1, so a body without a `Content-Length` is then buffered until the
+    /// request is finished, and sent with one.
+    pub fn version(mut self, major: u32, minor: u32) -> Self {
+        self.request.set_version(major, minor);
+        let chunked = (major, minor) >= (1, 1)
+            && self.request.method.has_body()
+            && self.request.headers.get("Content-Length").is_none();
+        if chunked {
+            self.request.add_header("Transfer-Encoding", "chunked");
+        } else {
+            self.request.headers.remove("Transfer-Encoding");
+        }
+        self
+    }
+
+    /// Add an `Authorization` header using the Basic scheme with the given credentials.
+    pub fn basic_auth(self, username: &str, password: Option<&str>) -> Self {
+        let credentials = format!("{}:{}", username, password.unwrap_or(""));
+        let value = format!("Basic {}", crate::base64::encode(credentials.as_bytes()));
+        self.add_header("Authorization", value)
+    }
+
+    /// Append the given parameters to the query string of the url, percent-encoding them as
+    /// needed. Any query already present on the url is kept.
+    pub fn query<I, K, V>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.url.query_pairs_mut().extend_pairs(params);
+        self.request.uri = request_target(&self.url);
+        self
+    }
+
+    /// Use the given bytes as the body of the request. It is sent with a `Content-Length` instead
+    /// of being chunk encoded.
+    pub fn body(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        let bytes = bytes.into();
+        self.request.headers.remove("Transfer-Encoding");
+        self.request
+            .add_header("Content-Length", bytes.len().to_string());
+        self.body = Some(Box::new(io::Cursor::new(bytes)));
+        self
+    }
+
+    /// Use the given multipart form as the body of the request, and set the Content-Type
+    /// accordingly. The body is streamed using chunked encoding as it is sent.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn multipart(mut self, form: crate::multipart::MultipartBuilder) -> Self {
+        self = self.add_header("Content-Type", form.content_type());
+        self.body = Some(Box::new(form.into_body()));
+        self
+    }
+
+    /// Percent-encode the given fields to use as an `application/x-www-form-urlencoded` body, and
+    /// set the Content-Type accordingly.
+    pub fn form<I, K, V>(self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut body = String::new();
+        for (key, value) in fields {
+            if !body.is_empty() {
+                body.push('&');
+            }
+            body.push_str(&encode_component(key.as_ref()));
+            body.push('=');
+            body.push_str(&encode_component(value.as_ref()));
+        }
+        self.add_header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    /// Serialize the given value as JSON to use as the body of the request, and set the
+    /// Content-Type accordingly.
+    ///
+    /// *This function is available if http_io is built with the `"json"` feature.*
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(self, value: &T) -> Result<Self> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self
+            .add_header("Content-Type", "application/json")
+            .body(body))
+    }
+
+    /// Advertise the given content codings in the `Accept-Encoding` header, most preferred first.
+    /// Pass the same encodings to `HttpResponse::decode` to undo whichever one the server used.
+    pub fn accept_encoding(self, encodings: &[&str]) -> Self {
+        self.add_header("Accept-Encoding", encodings.join(", "))
     }
 
     /// Add a header to the request
@@ -149,6 +316,23 @@ impl HttpRequestBuilder {
         self.request.add_header(key.as_ref(), value.as_ref());
         self
     }
+
+    /// Remove a header from the request, including any of the headers added by default.
+    pub fn remove_header<S: AsRef<str>>(mut self, key: S) -> Self {
+        self.request.headers.remove(key.as_ref());
+        self
+    }
+
+    /// Remove the `User-Agent` and `Accept` headers added by default. `Host` is mandatory so it is
+    /// kept, as is `Transfer-Encoding` when the request has a body.
+    pub fn without_default_headers(self) -> Self {
+        self.remove_header("User-Agent").remove_header("Accept")
+    }
+
+    /// The headers the request will be sent with, including the ones added by default.
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.request.headers
+    }
 }
 
 /// Represents the ability to connect an abstract stream to some destination address.
@@ -157,6 +341,194 @@ pub trait StreamConnector {
     type StreamAddr: Hash + Eq + Clone;
     fn connect(a: Self::StreamAddr) -> Result<Self::Stream>;
     fn to_stream_addr(url: Url) -> Result<Self::StreamAddr>;
+
+    /// Like `to_stream_addr`, but the stream goes through the HTTP proxy at `proxy`. Streams for
+    /// `https` urls are tunneled through the proxy with `CONNECT`, other streams are just to the
+    /// proxy. Implementations which don't support proxies return an error.
+    fn to_proxied_stream_addr(_url: Url, _proxy: Url) -> Result<Self::StreamAddr> {
+        Err(Error::Other("proxies are not supported".into()))
+    }
+
+    /// Reorder the addresses `a` resolved to, so that connecting tries the ones of the preferred
+    /// family first. Implementations which don't connect to IP addresses ignore it.
+    fn prefer_address_family(_a: &mut Self::StreamAddr, _preference: AddressFamily) {}
+
+    /// Like `connect`, but give up on connecting after `connect_timeout` and on reading after
+    /// `read_timeout`. Implementations which don't support timeouts ignore them.
+    fn connect_with_timeouts(
+        a: Self::StreamAddr,
+        _connect_timeout: Option<Duration>,
+        _read_timeout: Option<Duration>,
+    ) -> Result<Self::Stream> {
+        Self::connect(a)
+    }
+
+    /// Whether a stream kept open after an earlier request can no longer be used, for instance
+    /// because the server closed it. Implementations which can't tell return false.
+    fn is_stale(_stream: &Self::Stream) -> bool {
+        false
+    }
+
+    /// Make reads from the stream give up after `timeout`, or never if it is `None`. Used to stop
+    /// waiting for a server to answer `Expect: 100-continue`. Implementations which don't support
+    /// timeouts ignore it.
+    fn set_read_timeout(_stream: &Self::Stream, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Like `connect_with_timeouts`, but secure streams use the given TLS configuration.
+    /// Implementations which don't use TLS ignore it.
+    ///
+    /// *This function is available if http_io is built with an `"ssl"` feature.*
+    #[cfg(feature = "ssl")]
+    fn connect_with_ssl_config(
+        a: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+        _ssl_config: &crate::ssl::SslClientConfig,
+    ) -> Result<Self::Stream> {
+        Self::connect_with_timeouts(a, connect_timeout, read_timeout)
+    }
+}
+
+/// Which kind of IP address to try connecting to first when a host resolves to both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressFamily {
+    /// Keep the order the resolver returned the addresses in.
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+/// Which way bytes given to a `StreamLogger` went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamDirection {
+    Read,
+    Write,
+}
+
+/// Sees the bytes read from and written to a `LoggingStream`. It is implemented for closures.
+pub trait StreamLogger {
+    fn log(&mut self, direction: StreamDirection, bytes: &[u8]);
+}
+
+impl<F: FnMut(StreamDirection, &[u8])> StreamLogger for F {
+    fn log(&mut self, direction: StreamDirection, bytes: &[u8]) {
+        self(direction, bytes)
+    }
+}
+
+/// A stream which passes a copy of everything read from or written to it to a logger, for
+/// inspecting the raw bytes of requests and responses.
+///
+/// It is also a `StreamConnector` wrapping another, so a `HttpClient` can log everything it sends
+/// and receives. Each new connection gets its own `L::default()` logger, for example:
+/// `HttpClient::<LoggingStream<std::net::TcpStream, MyLogger>>::new()`, or a clone of the one
+/// given to `HttpClient::with_logger`.
+pub struct LoggingStream<S, L> {
+    inner: S,
+    logger: L,
+}
+
+impl<S, L> LoggingStream<S, L> {
+    pub fn new(inner: S, logger: L) -> Self {
+        Self { inner, logger }
+    }
+
+    /// The wrapped stream. Reading from or writing to it directly bypasses the logger.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the stream, dropping the logger.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: io::Read, L: StreamLogger> io::Read for LoggingStream<S, L> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.logger.log(StreamDirection::Read, &buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<S: io::Write, L: StreamLogger> io::Write for LoggingStream<S, L> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.logger.log(StreamDirection::Write, &buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: StreamConnector, L: StreamLogger + Default> StreamConnector for LoggingStream<S, L> {
+    type Stream = LoggingStream<S::Stream, L>;
+    type StreamAddr = S::StreamAddr;
+
+    fn connect(a: Self::StreamAddr) -> Result<Self::Stream> {
+        Ok(LoggingStream::new(S::connect(a)?, L::default()))
+    }
+
+    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr> {
+        S::to_stream_addr(url)
+    }
+
+    fn to_proxied_stream_addr(url: Url, proxy: Url) -> Result<Self::StreamAddr> {
+        S::to_proxied_stream_addr(url, proxy)
+    }
+
+    fn prefer_address_family(a: &mut Self::StreamAddr, preference: AddressFamily) {
+        S::prefer_address_family(a, preference)
+    }
+
+    fn connect_with_timeouts(
+        a: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) -> Result<Self::Stream> {
+        let stream = S::connect_with_timeouts(a, connect_timeout, read_timeout)?;
+        Ok(LoggingStream::new(stream, L::default()))
+    }
+
+    fn is_stale(stream: &Self::Stream) -> bool {
+        S::is_stale(&stream.inner)
+    }
+
+    fn set_read_timeout(stream: &Self::Stream, timeout: Option<Duration>) -> Result<()> {
+        S::set_read_timeout(&stream.inner, timeout)
+    }
+
+    #[cfg(feature = "ssl")]
+    fn connect_with_ssl_config(
+        a: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+        ssl_config: &crate::ssl::SslClientConfig,
+    ) -> Result<Self::Stream> {
+        let stream = S::connect_with_ssl_config(a, connect_timeout, read_timeout, ssl_config)?;
+        Ok(LoggingStream::new(stream, L::default()))
+    }
+}
+
+impl<S, L> HttpClient<LoggingStream<S, L>>
+where
+    S: StreamConnector,
+    L: StreamLogger + Default + Clone + Send + Sync + 'static,
+{
+    /// Create an `HTTPClient` whose connections each log to a clone of `logger`, instead of to a
+    /// new `L::default()`. A logger whose clones share their state, for instance through an `Arc`,
+    /// sees everything this client sends and receives and nothing from other clients.
+    pub fn with_logger(logger: L) -> Self {
+        let mut client = Self::new();
+        client.on_connect = Some(Box::new(move |stream| stream.logger = logger.clone()));
+        client
+    }
 }
 
 pub enum StreamEither<A, B> {
@@ -194,6 +566,8 @@ pub struct StreamId<Addr> {
     addr: Addr,
     host: String,
     secure: bool,
+    /// The `host:port` to ask the proxy at `addr` to open a tunnel to.
+    tunnel: Option<String>,
 }
 
 #[cfg(all(feature = "std", feature = "ssl"))]
@@ -203,28 +577,141 @@ pub type StdTransport =
 #[cfg(all(feature = "std", not(feature = "ssl")))]
 pub type StdTransport = std::net::TcpStream;
 
+/// Connect to the first of the addresses which accepts the connection, trying them in order.
+/// Fails with the error from the last address if none do.
+#[cfg(feature = "std")]
+fn connect_tcp(
+    addrs: &[std::net::SocketAddr],
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+) -> Result<std::net::TcpStream> {
+    let mut last_error = std::io::Error::new(
+        std::io::ErrorKind::AddrNotAvailable,
+        "no addresses to connect to",
+    );
+    for addr in addrs {
+        let result = match connect_timeout {
+            Some(timeout) => std::net::TcpStream::connect_timeout(addr, timeout),
+            None => std::net::TcpStream::connect(addr),
+        };
+        match result {
+            Ok(s) => {
+                s.set_read_timeout(read_timeout)?;
+                return Ok(s);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error.into())
+}
+
+/// Nothing should arrive on an idle connection, so anything to read, even the end of the stream,
+/// means the server is done with it.
+#[cfg(feature = "std")]
+fn tcp_is_stale(stream: &std::net::TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let idle = matches!(
+        stream.peek(&mut [0]),
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+    );
+    stream.set_nonblocking(false).is_err() || !idle
+}
+
+/// Ask the HTTP proxy on the other end of the stream to open a tunnel to `target`, see RFC 7231
+/// section 4.3.6.
+#[cfg(feature = "std")]
+fn open_tunnel(stream: &mut std::net::TcpStream, target: &str) -> Result<()> {
+    let mut request = HttpRequest::new(HttpMethod::Connect, target);
+    request.add_header("Host", target);
+    let response = request
+        .serialize(io::BufWriter::new(&mut *stream))?
+        .finish()?;
+    if !response.status.is_success() {
+        return Err(Error::UnexpectedStatus(response.status));
+    }
+    Ok(())
+}
+
 #[cfg(feature = "std")]
 impl StreamConnector for std::net::TcpStream {
     type Stream = StdTransport;
-    type StreamAddr = StreamId<std::net::SocketAddr>;
+    type StreamAddr = StreamId<Vec<std::net::SocketAddr>>;
 
-    #[cfg(not(feature = "ssl"))]
     fn connect(id: Self::StreamAddr) -> Result<Self::Stream> {
-        Ok(std::net::TcpStream::connect(id.addr)?)
+        Self::connect_with_timeouts(id, None, None)
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    fn connect_with_timeouts(
+        id: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) -> Result<Self::Stream> {
+        let mut s = connect_tcp(&id.addr, connect_timeout, read_timeout)?;
+        if let Some(target) = &id.tunnel {
+            open_tunnel(&mut s, target)?;
+        }
+        Ok(s)
     }
 
     #[cfg(feature = "ssl")]
-    fn connect(id: Self::StreamAddr) -> Result<Self::Stream> {
-        let s = std::net::TcpStream::connect(id.addr)?;
+    fn connect_with_timeouts(
+        id: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) -> Result<Self::Stream> {
+        Self::connect_with_ssl_config(id, connect_timeout, read_timeout, &Default::default())
+    }
+
+    #[cfg(feature = "ssl")]
+    fn connect_with_ssl_config(
+        id: Self::StreamAddr,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+        ssl_config: &crate::ssl::SslClientConfig,
+    ) -> Result<Self::Stream> {
+        let mut s = connect_tcp(&id.addr, connect_timeout, read_timeout)?;
+        if let Some(target) = &id.tunnel {
+            open_tunnel(&mut s, target)?;
+        }
         if id.secure {
-            Ok(StreamEither::B(crate::ssl::SslClientStream::new(
-                &id.host, s,
+            Ok(StreamEither::B(crate::ssl::SslClientStream::with_config(
+                &id.host, s, ssl_config,
             )?))
         } else {
             Ok(StreamEither::A(s))
         }
     }
 
+    #[cfg(not(feature = "ssl"))]
+    fn is_stale(stream: &Self::Stream) -> bool {
+        tcp_is_stale(stream)
+    }
+
+    #[cfg(feature = "ssl")]
+    fn is_stale(stream: &Self::Stream) -> bool {
+        match stream {
+            StreamEither::A(s) => tcp_is_stale(s),
+            StreamEither::B(s) => tcp_is_stale(s.get_ref()),
+        }
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    fn set_read_timeout(stream: &Self::Stream, timeout: Option<Duration>) -> Result<()> {
+        Ok(stream.set_read_timeout(timeout)?)
+    }
+
+    #[cfg(feature = "ssl")]
+    fn set_read_timeout(stream: &Self::Stream, timeout: Option<Duration>) -> Result<()> {
+        match stream {
+            StreamEither::A(s) => s.set_read_timeout(timeout)?,
+            StreamEither::B(s) => s.get_ref().set_read_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
     fn to_stream_addr(url: Url) -> Result<Self::StreamAddr> {
         use core::convert::TryFrom;
 
@@ -236,112 +723,1248 @@ impl StreamConnector for std::net::TcpStream {
             )
         };
 
-        Ok(StreamId {
-            addr: std::net::ToSocketAddrs::to_socket_addrs(&(http_url.host(), http_url.port()))
+        let addr: Vec<_> =
+            std::net::ToSocketAddrs::to_socket_addrs(&(http_url.socket_host(), http_url.port()))
                 .map_err(|_| err())?
-                .next()
-                .ok_or_else(err)?,
-            host: String::from(http_url.host()),
+                .collect();
+        if addr.is_empty() {
+            return Err(err().into());
+        }
+        Ok(StreamId {
+            addr,
+            host: String::from(http_url.socket_host()),
             secure: Scheme::Https.eq(&http_url.scheme()),
+            tunnel: None,
         })
     }
+
+    fn to_proxied_stream_addr(url: Url, proxy: Url) -> Result<Self::StreamAddr> {
+        use core::convert::TryFrom;
+
+        let mut id = Self::to_stream_addr(proxy)?;
+        if id.secure {
+            return Err(Error::Other("only http proxies are supported".into()));
+        }
+
+        let http_url = HttpUrl::try_from(url)?;
+        if http_url.scheme() == Scheme::Https {
+            id.host = String::from(http_url.socket_host());
+            id.secure = true;
+            id.tunnel = Some(format!("{}:{}", http_url.host(), http_url.port()));
+        }
+        Ok(id)
+    }
+
+    fn prefer_address_family(id: &mut Self::StreamAddr, preference: AddressFamily) {
+        // The sort is stable, so addresses of the same family keep the resolver's order.
+        match preference {
+            AddressFamily::Any => {}
+            AddressFamily::Ipv4 => id.addr.sort_by_key(|a| !a.is_ipv4()),
+            AddressFamily::Ipv6 => id.addr.sort_by_key(|a| !a.is_ipv6()),
+        }
+    }
+}
+
+/// The number of redirects followed before giving up with `Error::TooManyRedirects`, unless
+/// configured otherwise.
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// How long `HttpClient` waits for a server to answer `Expect: 100-continue` before sending the
+/// body anyway.
+pub const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The content codings `HttpResponse::decode` knows how to undo.
+///
+/// *This constant is available if http_io is built with the `"flate"` feature.*
+#[cfg(feature = "flate")]
+pub const DECODABLE_ENCODINGS: &[&str] = &["gzip", "deflate"];
+
+/// If the given response is a redirect, returns the method and URL the request should be re-issued
+/// with.
+fn redirect_target<B: io::Read>(
+    method: HttpMethod,
+    url: &Url,
+    response: &HttpResponse<B>,
+) -> Result<Option<(HttpMethod, Url)>> {
+    let method = match response.status {
+        HttpStatus::MovedPermanently | HttpStatus::Found => method,
+        HttpStatus::SeeOther if method == HttpMethod::Head => method,
+        HttpStatus::SeeOther => HttpMethod::Get,
+        HttpStatus::TemporaryRedirect | HttpStatus::PermanentRedirect => method,
+        _ => return Ok(None),
+    };
+    let location = response
+        .get_header("Location")
+        .ok_or_else(|| Error::ParseError("redirect without a Location header".into()))?;
+    Ok(Some((method, url.join(location)?)))
 }
 
 /// An HTTP client that keeps connections open.
 pub struct HttpClient<S: StreamConnector> {
     streams: HashMap<S::StreamAddr, S::Stream>,
+    /// Streams which the server is going to close after the response being read from them.
+    closing: HashSet<S::StreamAddr>,
+    max_redirects: usize,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    default_headers: HttpHeaders,
+    proxy: Option<Url>,
+    address_family: AddressFamily,
+    max_response_bytes: Option<u64>,
+    /// Called with every new stream, see `HttpClient::with_logger`.
+    on_connect: Option<OnConnect<S::Stream>>,
+    #[cfg(feature = "ssl")]
+    ssl_config: crate::ssl::SslClientConfig,
 }
 
-impl<S: StreamConnector> HttpClient<S> {
-    /// Create an `HTTPClient`
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        Self {
-            streams: HashMap::new(),
-        }
+/// A request sent on one of the streams of a `HttpClient`, see `OutgoingRequest`. When it is
+/// finished, the client takes note if the server is going to close the connection after the
+/// response, so that the stream isn't used for any more requests. The response body is limited as
+/// configured with `HttpClient::set_max_response_bytes`.
+pub struct ClientRequest<'a, S: StreamConnector> {
+    request: OutgoingRequest<&'a mut S::Stream>,
+    stream_addr: S::StreamAddr,
+    closing: &'a mut HashSet<S::StreamAddr>,
+    max_response_bytes: Option<u64>,
+}
+
+impl<'a, S: StreamConnector> ClientRequest<'a, S> {
+    /// See `OutgoingBody::set_chunk_size`. It does nothing for requests without a body.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.request.set_chunk_size(chunk_size);
     }
 
-    fn get_stream(&mut self, url: Url) -> Result<&mut S::Stream> {
-        let stream_addr = S::to_stream_addr(url)?;
-        if !self.streams.contains_key(&stream_addr) {
-            let stream = S::connect(stream_addr.clone())?;
-            self.streams.insert(stream_addr.clone(), stream);
-        }
-        Ok(self.streams.get_mut(&stream_addr).unwrap())
+    pub fn finish(self) -> Result<HttpResponse<&'a mut S::Stream>> {
+        let response = self.request.finish();
+        Self::finished(
+            self.closing,
+            self.stream_addr,
+            self.max_response_bytes,
+            response,
+        )
     }
 
-    /// Execute a GET request. The request isn't completed until `OutgoingRequest::finish` is
-    /// called.
-    pub fn get<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingRequest<&mut S::Stream>>
-    where
-        <U as TryInto<Url>>::Error: Display,
-    {
-        let url = url
-            .try_into()
-            .map_err(|e| Error::ParseError(e.to_string()))?;
-        Ok(HttpRequestBuilder::get(url.clone())?.send(self.get_stream(url)?)?)
+    /// Like `finish`, but send the given trailer headers after the body, see
+    /// `OutgoingBody::finish_with_trailers`.
+    pub fn finish_with_trailers(
+        self,
+        trailers: &HttpHeaders,
+    ) -> Result<HttpResponse<&'a mut S::Stream>> {
+        let response = self.request.finish_with_trailers(trailers);
+        Self::finished(
+            self.closing,
+            self.stream_addr,
+            self.max_response_bytes,
+            response,
+        )
     }
 
-    /// Execute a PUT request. The request isn't completed until `OutgoingRequest::finish` is
-    /// called.
-    pub fn put<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingRequest<&mut S::Stream>>
-    where
-        <U as TryInto<Url>>::Error: Display,
-    {
-        let url = url
-            .try_into()
-            .map_err(|e| Error::ParseError(e.to_string()))?;
-        Ok(HttpRequestBuilder::put(url.clone())?.send(self.get_stream(url)?)?)
+    /// Stop using the stream if the server is going to close it, or if it is left in an unknown
+    /// state by an error. Then limit the size of the body.
+    fn finished(
+        closing: &mut HashSet<S::StreamAddr>,
+        stream_addr: S::StreamAddr,
+        max_response_bytes: Option<u64>,
+        response: Result<HttpResponse<&'a mut S::Stream>>,
+    ) -> Result<HttpResponse<&'a mut S::Stream>> {
+        let response = response.map_err(Error::read_timed_out);
+        if !response.as_ref().is_ok_and(|r| r.keep_alive()) {
+            closing.insert(stream_addr);
+        }
+        limit_response(response?, max_response_bytes)
     }
 }
 
-#[cfg(feature = "std")]
-fn send_request<R: io::Read>(
-    builder: HttpRequestBuilder,
-    url: Url,
-    mut body: R,
-) -> Result<HttpBody<StdTransport>> {
-    use std::net::TcpStream;
+impl<S: StreamConnector> io::Write for ClientRequest<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.request.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.request.flush()
+    }
+}
 
-    let stream = <TcpStream as StreamConnector>::connect(TcpStream::to_stream_addr(url)?)?;
-    let mut request = builder.send(stream)?;
-    io::copy(&mut body, &mut request)?;
-    let response = request.finish()?;
+type OnConnect<T> = Box<dyn Fn(&mut T) + Send + Sync>;
 
-    if response.status != HttpStatus::OK {
-        return Err(Error::UnexpectedStatus(response.status));
+/// The read timeout to use while `waiting` for the server to answer `Expect: 100-continue`, and
+/// once done waiting.
+fn continue_wait_timeout(waiting: bool, read_timeout: Option<Duration>) -> Option<Duration> {
+    match (waiting, read_timeout) {
+        (true, Some(t)) => Some(t.min(CONTINUE_TIMEOUT)),
+        (true, None) => Some(CONTINUE_TIMEOUT),
+        (false, t) => t,
     }
+}
 
-    Ok(response.body)
+/// A stream a request is sent on, watched for signs the server had already closed it.
+struct WatchedStream<'a, T> {
+    inner: &'a mut T,
+    write_failed: bool,
+    read_any: bool,
+    ended: bool,
 }
 
-#[cfg(test)]
-use crate::server::{
-    test_server, test_ssl_server, ExpectedRequest, HttpRequestHandler, HttpServer, Listen,
-};
+impl<'a, T> WatchedStream<'a, T> {
+    fn new(inner: &'a mut T) -> Self {
+        Self {
+            inner,
+            write_failed: false,
+            read_any: false,
+            ended: false,
+        }
+    }
 
-#[cfg(test)]
-use crate::http_headers;
+    /// Whether writing the request failed, or the stream ended before any of the response
+    /// arrived. If the stream was kept open from an earlier request, that means the server closed
+    /// it without us noticing, and the request can be sent again on a new stream. Other errors,
+    /// like timing out waiting for the response, leave it unknown whether the server got the
+    /// request.
+    fn closed_early(&self) -> bool {
+        self.write_failed || (self.ended && !self.read_any)
+    }
+}
 
-/// Execute a GET request.
-///
-/// *This function is available if http_io is built with the `"std"` feature.*
-#[cfg(feature = "std")]
-pub fn get<U: TryInto<Url>>(url: U) -> Result<HttpBody<StdTransport>>
-where
-    <U as TryInto<Url>>::Error: Display,
-{
-    let url = url
-        .try_into()
-        .map_err(|e| Error::ParseError(e.to_string()))?;
-    let builder = HttpRequestBuilder::get(url.clone())?;
-    Ok(send_request(builder, url, io::empty())?)
+impl<T: io::Read> io::Read for WatchedStream<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if len > 0 {
+            self.read_any = true;
+        } else if !buf.is_empty() {
+            self.ended = true;
+        }
+        Ok(len)
+    }
 }
 
-#[cfg(test)]
-fn get_test<
-    L: Listen + Send + 'static,
-    T: HttpRequestHandler<L::Stream> + Send + 'static,
+impl<T: io::Write> io::Write for WatchedStream<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.write_failed |= result.is_err();
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.write_failed |= result.is_err();
+        result
+    }
+}
+
+/// Limit the size of the body of the response, see `HttpClient::set_max_response_bytes`.
+fn limit_response<B: io::Read>(
+    mut response: HttpResponse<B>,
+    max_response_bytes: Option<u64>,
+) -> Result<HttpResponse<B>> {
+    if let Some(max) = max_response_bytes {
+        response.body.limit_size(max)?;
+    }
+    Ok(response)
+}
+
+impl<S: StreamConnector> HttpClient<S> {
+    /// Create an `HTTPClient`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_timeouts(None, None)
+    }
+
+    /// Create an `HTTPClient` which gives up on connecting after `connect` and on waiting for data
+    /// after `read`. When a timeout is hit, `Error::Timeout` is returned.
+    pub fn with_timeouts(connect: Option<Duration>, read: Option<Duration>) -> Self {
+        Self {
+            streams: HashMap::new(),
+            closing: HashSet::new(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            connect_timeout: connect,
+            read_timeout: read,
+            default_headers: HttpHeaders::default(),
+            proxy: None,
+            address_family: AddressFamily::Any,
+            max_response_bytes: None,
+            on_connect: None,
+            #[cfg(feature = "ssl")]
+            ssl_config: Default::default(),
+        }
+    }
+
+    /// Send requests through the HTTP proxy at the given url, or directly if `None`. Requests for
+    /// `https` urls are tunneled through the proxy with `CONNECT`. Connections already open are
+    /// unaffected.
+    pub fn set_proxy(&mut self, proxy: Option<Url>) {
+        self.proxy = proxy;
+    }
+
+    /// When a host resolves to several addresses, try connecting to the ones of the given family
+    /// first. The others are still tried if none of those work.
+    pub fn set_address_family(&mut self, address_family: AddressFamily) {
+        self.address_family = address_family;
+    }
+
+    /// Fail with `Error::BodyTooLarge` rather than read more than `max_response_bytes` bytes of the
+    /// body of a response, whatever its `Content-Length` says. It applies to every response the
+    /// client hands back, including those from `ClientRequest::finish`. The free functions like
+    /// `get` don't use a client, so for them use `HttpBody::limit_size`.
+    pub fn set_max_response_bytes(&mut self, max_response_bytes: Option<u64>) {
+        self.max_response_bytes = max_response_bytes;
+    }
+
+    /// Use the given TLS configuration for new secure connections. Connections already open are
+    /// unaffected.
+    ///
+    /// *This function is available if http_io is built with an `"ssl"` feature.*
+    #[cfg(feature = "ssl")]
+    pub fn set_ssl_config(&mut self, ssl_config: crate::ssl::SslClientConfig) {
+        self.ssl_config = ssl_config;
+    }
+
+    /// Create an `HTTPClient` which uses the given TLS configuration for secure connections.
+    ///
+    /// *This function is available if http_io is built with an `"ssl"` feature.*
+    #[cfg(feature = "ssl")]
+    pub fn with_ssl_config(ssl_config: crate::ssl::SslClientConfig) -> Self {
+        let mut client = Self::new();
+        client.set_ssl_config(ssl_config);
+        client
+    }
+
+    /// Add a header to every request made with this client. It replaces any header of the same
+    /// name the `HttpRequestBuilder` adds by default, but headers added to an individual request
+    /// take precedence over it.
+    pub fn set_default_header(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+        self.default_headers.insert(key, value);
+    }
+
+    /// Authenticate every request made with this client using the given bearer token.
+    pub fn set_bearer_token(&mut self, token: &str) {
+        self.set_default_header("Authorization", format!("Bearer {}", token));
+    }
+
+    /// Advertise `Accept-Encoding: gzip, deflate` on every request made with this client. Use
+    /// `HttpResponse::decode` with `DECODABLE_ENCODINGS` to decompress the responses.
+    ///
+    /// *This function is available if http_io is built with the `"flate"` feature.*
+    #[cfg(feature = "flate")]
+    pub fn set_accept_compressed(&mut self) {
+        self.set_default_header("Accept-Encoding", DECODABLE_ENCODINGS.join(", "));
+    }
+
+    /// Create a `HttpRequestBuilder` with the client's default headers added. Send it using
+    /// `HttpClient::send`.
+    pub fn request<U: TryInto<Url>>(&self, method: HttpMethod, url: U) -> Result<HttpRequestBuilder>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let url = url
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        let mut builder = HttpRequestBuilder::new(url, method)?;
+        builder.request.headers.merge(&self.default_headers);
+        Ok(builder)
+    }
+
+    /// Send the request on a connection to the host it is for. The request isn't completed until
+    /// `ClientRequest::finish` is called.
+    pub fn send(&mut self, mut builder: HttpRequestBuilder) -> Result<ClientRequest<'_, S>> {
+        let stream_addr = self.route(&mut builder)?;
+        let read_timeout = self.read_timeout;
+        self.get_stream_for_addr(stream_addr.clone())?;
+        let stream = self.streams.get_mut(&stream_addr).unwrap();
+        let request = builder.send_with_continue_wait(stream, |stream, waiting| {
+            S::set_read_timeout(stream, continue_wait_timeout(waiting, read_timeout))
+        })?;
+        Ok(ClientRequest {
+            request,
+            stream_addr,
+            closing: &mut self.closing,
+            max_response_bytes: self.max_response_bytes,
+        })
+    }
+
+    /// Send the request with the given body and wait for the head of the response. The response
+    /// is returned detached from the stream, along with the address of the stream it is to be read
+    /// from. The body is recorded as it is sent, see `copy_and_record`.
+    ///
+    /// On failure the stream is dropped. The error comes with whether the stream was kept open from
+    /// an earlier request and the server turned out to have closed it, see
+    /// `WatchedStream::closed_early`.
+    fn send_once(
+        &mut self,
+        mut builder: HttpRequestBuilder,
+        body: &mut dyn io::Read,
+        record: &mut Option<Vec<u8>>,
+    ) -> core::result::Result<(S::StreamAddr, HttpResponse<io::Empty>), (Error, bool)> {
+        let stream_addr = self.route(&mut builder).map_err(|e| (e, false))?;
+        let read_timeout = self.read_timeout;
+        let (stream, reused) = self
+            .get_stream_for_addr(stream_addr.clone())
+            .map_err(|e| (e, false))?;
+        let mut stream = WatchedStream::new(stream);
+        let result = builder
+            .send_with_continue_wait(&mut stream, |stream, waiting| {
+                S::set_read_timeout(stream.inner, continue_wait_timeout(waiting, read_timeout))
+            })
+            .and_then(|mut request| {
+                copy_and_record(body, &mut request, record)?;
+                request.finish().map_err(Error::read_timed_out)
+            })
+            .and_then(|response| response.reattach(io::empty()));
+        match result {
+            Ok(response) => Ok((stream_addr, response)),
+            Err(e) => {
+                let closed_early = reused && stream.closed_early();
+                self.streams.remove(&stream_addr);
+                Err((e, closed_early))
+            }
+        }
+    }
+
+    /// Like `send`, but returns the request as it is along with the address of the stream it was
+    /// sent on, leaving it to the caller to deal with the response.
+    fn send_to(
+        &mut self,
+        builder: HttpRequestBuilder,
+    ) -> Result<(S::StreamAddr, OutgoingRequest<&mut S::Stream>)> {
+        let request = self.send(builder)?;
+        Ok((request.stream_addr, request.request))
+    }
+
+    /// Hand back the head of a response along with the stream its body is to be read from. The
+    /// response is passed detached from the stream, so that the client isn't borrowed by it. If the
+    /// server is going to close the connection after the response, the stream isn't used for any
+    /// more requests.
+    fn reattach_response(
+        &mut self,
+        stream_addr: &S::StreamAddr,
+        response: HttpResponse<io::Empty>,
+    ) -> Result<HttpResponse<&mut S::Stream>> {
+        if !response.keep_alive() {
+            self.closing.insert(stream_addr.clone());
+        }
+        let stream = self.streams.get_mut(stream_addr).unwrap();
+        limit_response(response.reattach(stream)?, self.max_response_bytes)
+    }
+
+    /// Find the stream the request should be sent on. Requests sent to a proxy rather than
+    /// through a tunnel are changed to use the absolute url as their target.
+    fn route(&self, builder: &mut HttpRequestBuilder) -> Result<S::StreamAddr> {
+        let mut stream_addr = match &self.proxy {
+            Some(proxy) => {
+                if builder.url.scheme() != "https" {
+                    let mut target = builder.url.clone();
+                    target.set_fragment(None);
+                    builder.request.uri = target.to_string();
+                }
+                S::to_proxied_stream_addr(builder.url.clone(), proxy.clone())
+            }
+            None => S::to_stream_addr(builder.url.clone()),
+        }?;
+        S::prefer_address_family(&mut stream_addr, self.address_family);
+        Ok(stream_addr)
+    }
+
+    /// Set the number of redirects `get_following_redirects` will follow.
+    pub fn set_max_redirects(&mut self, max_redirects: usize) {
+        self.max_redirects = max_redirects;
+    }
+
+    /// Get the stream kept open for the address, or connect a new one if there isn't one or it
+    /// has gone stale. Also returns whether the stream was kept open from an earlier request.
+    fn get_stream_for_addr(
+        &mut self,
+        stream_addr: S::StreamAddr,
+    ) -> Result<(&mut S::Stream, bool)> {
+        if self.closing.remove(&stream_addr)
+            || self.streams.get(&stream_addr).is_some_and(S::is_stale)
+        {
+            self.streams.remove(&stream_addr);
+        }
+        let reused = self.streams.contains_key(&stream_addr);
+        if !reused {
+            let mut stream = self.connect(stream_addr.clone())?;
+            if let Some(on_connect) = &self.on_connect {
+                on_connect(&mut stream);
+            }
+            self.streams.insert(stream_addr.clone(), stream);
+        }
+        Ok((self.streams.get_mut(&stream_addr).unwrap(), reused))
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    fn connect(&self, stream_addr: S::StreamAddr) -> Result<S::Stream> {
+        S::connect_with_timeouts(stream_addr, self.connect_timeout, self.read_timeout)
+    }
+
+    #[cfg(feature = "ssl")]
+    fn connect(&self, stream_addr: S::StreamAddr) -> Result<S::Stream> {
+        S::connect_with_ssl_config(
+            stream_addr,
+            self.connect_timeout,
+            self.read_timeout,
+            &self.ssl_config,
+        )
+    }
+
+    /// Execute a DELETE request. The request isn't completed until `ClientRequest::finish` is
+    /// called.
+    pub fn delete<U: TryInto<Url>>(&mut self, url: U) -> Result<ClientRequest<'_, S>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let builder = self.request(HttpMethod::Delete, url)?;
+        self.send(builder)
+    }
+
+    /// Execute a HEAD request and wait for the response. Its headers describe the body a GET would
+    /// get, but the body itself is always empty.
+    pub fn head<U: TryInto<Url>>(&mut self, url: U) -> Result<HttpResponse<&mut S::Stream>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let builder = self.request(HttpMethod::Head, url)?;
+        let (stream_addr, request) = self.send_to(builder)?;
+        let response = request
+            .finish()
+            .map_err(Error::read_timed_out)?
+            .reattach(io::empty())?;
+        self.reattach_response(&stream_addr, response)
+    }
+
+    /// Execute an OPTIONS request and wait for the response, which usually lists the supported
+    /// methods in its `Allow` header.
+    pub fn options<U: TryInto<Url>>(&mut self, url: U) -> Result<HttpResponse<&mut S::Stream>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let builder = self.request(HttpMethod::Options, url)?;
+        let (stream_addr, request) = self.send_to(builder)?;
+        let response = request
+            .finish()
+            .map_err(Error::read_timed_out)?
+            .reattach(io::empty())?;
+        self.reattach_response(&stream_addr, response)
+    }
+
+    /// Execute a GET request. The request isn't completed until `ClientRequest::finish` is
+    /// called.
+    pub fn get<U: TryInto<Url>>(&mut self, url: U) -> Result<ClientRequest<'_, S>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let builder = self.request(HttpMethod::Get, url)?;
+        self.send(builder)
+    }
+
+    /// Execute a GET request and wait for the response. If the server responds with a redirect,
+    /// the request is re-issued to the new location, up to the configured maximum number of
+    /// redirects. Once a redirect leads to another scheme, host or port, the `Authorization` and
+    /// `Cookie` headers are no longer sent, so credentials meant for one site don't leak to another.
+    pub fn get_following_redirects<U: TryInto<Url>>(
+        &mut self,
+        url: U,
+    ) -> Result<HttpResponse<&mut S::Stream>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let mut url = url
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        let origin = url.origin();
+        let mut same_origin = true;
+        for _ in 0..=self.max_redirects {
+            same_origin = same_origin && url.origin() == origin;
+            let (stream_addr, response) = self.get_once(&url, same_origin)?;
+            match redirect_target(HttpMethod::Get, &url, &response)? {
+                Some((_, next)) => {
+                    // Skip the body of the redirect, unless the connection is closing anyway.
+                    if response.keep_alive() {
+                        let stream = self.streams.get_mut(&stream_addr).unwrap();
+                        response.reattach(stream)?.body.drain()?;
+                    } else {
+                        self.streams.remove(&stream_addr);
+                    }
+                    url = next;
+                }
+                None => return self.reattach_response(&stream_addr, response),
+            }
+        }
+        Err(Error::TooManyRedirects)
+    }
+
+    /// Send a GET request and wait for the head of the response. The response is returned
+    /// detached from the stream, along with the address of the stream it is to be read from.
+    /// Credentials are only sent if `send_credentials` is true.
+    fn get_once(
+        &mut self,
+        url: &Url,
+        send_credentials: bool,
+    ) -> Result<(S::StreamAddr, HttpResponse<io::Empty>)> {
+        let mut builder = self.request(HttpMethod::Get, url.clone())?;
+        if !send_credentials {
+            builder = builder
+                .remove_header("Authorization")
+                .remove_header("Cookie");
+        }
+        match self.send_once(builder, &mut io::empty(), &mut None) {
+            Ok(sent) => Ok(sent),
+            // The server closed the connection kept open from an earlier request without us
+            // noticing, so try once more on a new one.
+            Err((_, true)) => self.get_once(url, send_credentials),
+            Err((e, false)) => Err(e),
+        }
+    }
+
+    /// Execute a PUT request. The request isn't completed until `ClientRequest::finish` is
+    /// called.
+    pub fn put<U: TryInto<Url>>(&mut self, url: U) -> Result<ClientRequest<'_, S>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let builder = self.request(HttpMethod::Put, url)?;
+        self.send(builder)
+    }
+
+    /// Execute a PUT request with the given body and wait for the response, see
+    /// `request_with_body`.
+    pub fn put_body<U: TryInto<Url>, R: io::Read>(
+        &mut self,
+        url: U,
+        body: R,
+    ) -> Result<HttpResponse<&mut S::Stream>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        self.request_with_body(HttpMethod::Put, url, body)
+    }
+
+    /// Execute a request with the given method, copy the body in to it and wait for the response.
+    /// Redirects aren't followed, since the body can only be read once. Fails without sending
+    /// anything if the body isn't empty and the method doesn't have one.
+    ///
+    /// If the server turns out to have closed the connection kept open from an earlier request,
+    /// the request is sent again on a new one, as long as no more than 64 KiB of the body had been
+    /// read.
+    pub fn request_with_body<U: TryInto<Url>, R: io::Read>(
+        &mut self,
+        method: HttpMethod,
+        url: U,
+        mut body: R,
+    ) -> Result<HttpResponse<&mut S::Stream>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        // Check before anything is sent, or the response would be left unread on the stream.
+        if !method.has_body() && body.read(&mut [0])? != 0 {
+            return Err(Error::Other("Method does not support a body".into()));
+        }
+        let url = url
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        let builder = self.request(method, url.clone())?;
+        let mut recorded = Some(Vec::new());
+        let (stream_addr, response) = match self.send_once(builder, &mut body, &mut recorded) {
+            Ok(sent) => sent,
+            // The server closed the connection kept open from an earlier request without us
+            // noticing. Try once more on a new one, if the body read so far was small enough to
+            // keep.
+            Err((e, closed_early)) => match recorded {
+                Some(recorded) if closed_early => {
+                    let builder = self.request(method, url)?;
+                    let mut body = io::Read::chain(&recorded[..], &mut body);
+                    self.send_once(builder, &mut body, &mut None)
+                        .map_err(|(e, _)| e)?
+                }
+                _ => return Err(e),
+            },
+        };
+        self.reattach_response(&stream_addr, response)
+    }
+}
+
+/// The most request body `send_request` keeps in memory, to send again if it is redirected, and
+/// `HttpClient::request_with_body` keeps to send again if the connection turns out to be closed.
+const MAX_RECORDED_BODY: usize = 64 * 1024;
+
+/// Like `io::copy`, but also keeps a copy of everything read in `record`, even if writing it
+/// fails. If more than `MAX_RECORDED_BODY` is read the copy is dropped and `record` is left as
+/// `None`.
+fn copy_and_record<R: io::Read + ?Sized, W: io::Write>(
+    reader: &mut R,
+    writer: &mut W,
+    record: &mut Option<Vec<u8>>,
+) -> Result<()> {
+    let mut buf = [0; 8 * 1024];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if let Some(recorded) = record {
+            if recorded.len() + len > MAX_RECORDED_BODY {
+                *record = None;
+            } else {
+                recorded.extend_from_slice(&buf[..len]);
+            }
+        }
+        writer.write_all(&buf[..len])?;
+    }
+}
+
+#[cfg(feature = "std")]
+/// Send a request, following redirects. If `rewind` is given it is used to send the body again
+/// after a redirect, otherwise small bodies are recorded as they are sent to send them again.
+fn send_request<R: io::Read>(
+    mut method: HttpMethod,
+    mut url: Url,
+    mut body: R,
+    rewind: Option<fn(&mut R) -> io::Result<()>>,
+    max_redirects: usize,
+) -> Result<HttpResponse<StdTransport>> {
+    use std::net::TcpStream;
+
+    // A redirect which keeps the method, like a 307 or 308, requires us to send the body again.
+    // Bodies too large to record can't be redirected unless they can be rewound.
+    let mut body_sent = false;
+    let mut sent_body = rewind.is_none().then(Vec::new);
+
+    for _ in 0..=max_redirects {
+        let stream =
+            <TcpStream as StreamConnector>::connect(TcpStream::to_stream_addr(url.clone())?)?;
+        let mut request = HttpRequestBuilder::new(url.clone(), method)?.send(stream)?;
+        if method.has_body() {
+            match (body_sent, rewind) {
+                (false, Some(_)) => {
+                    io::copy(&mut body, &mut request)?;
+                }
+                (false, None) => copy_and_record(&mut body, &mut request, &mut sent_body)?,
+                (true, Some(rewind)) => {
+                    rewind(&mut body)?;
+                    io::copy(&mut body, &mut request)?;
+                }
+                (true, None) => {
+                    let sent_body = sent_body.as_ref().expect("checked when redirected");
+                    io::copy(&mut &sent_body[..], &mut request)?;
+                }
+            }
+            body_sent = true;
+        }
+        let response = request.finish()?;
+
+        match redirect_target(method, &url, &response)? {
+            Some((next_method, next_url)) => {
+                if next_method.has_body() && body_sent && rewind.is_none() && sent_body.is_none() {
+                    return Err(Error::Other(format!(
+                        "the request body is too large to send again to the redirect location {}",
+                        next_url
+                    )));
+                }
+                method = next_method;
+                url = next_url;
+            }
+            None => return Ok(response),
+        }
+    }
+    Err(Error::TooManyRedirects)
+}
+
+/// Returns the body of the response, or `Error::UnexpectedStatus` if the status isn't OK.
+#[cfg(feature = "std")]
+fn expect_ok(response: HttpResponse<StdTransport>) -> Result<HttpBody<StdTransport>> {
+    if response.status != HttpStatus::OK {
+        return Err(Error::UnexpectedStatus(response.status));
+    }
+    Ok(response.body)
+}
+
+/// Execute a request with the given method, sending `body` if the method has one. Redirects are
+/// followed, up to `DEFAULT_MAX_REDIRECTS` of them, but a body larger than 64 KiB can't be sent
+/// again so a redirect which keeps the method fails. Unlike `get`, `put` and `delete`, the whole
+/// response is returned whatever its status is.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn request<U: TryInto<Url>, R: io::Read>(
+    method: HttpMethod,
+    url: U,
+    body: R,
+) -> Result<HttpResponse<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    request_with_max_redirects(method, url, body, DEFAULT_MAX_REDIRECTS)
+}
+
+/// Like `request`, but fail with `Error::TooManyRedirects` once more than `max_redirects`
+/// redirects would have to be followed, instead of more than `DEFAULT_MAX_REDIRECTS`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn request_with_max_redirects<U: TryInto<Url>, R: io::Read>(
+    method: HttpMethod,
+    url: U,
+    body: R,
+    max_redirects: usize,
+) -> Result<HttpResponse<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let url = url
+        .try_into()
+        .map_err(|e| Error::ParseError(e.to_string()))?;
+    send_request(method, url, body, None, max_redirects)
+}
+
+#[cfg(test)]
+use crate::server::{test_server, ExpectedRequest, HttpRequestHandler, HttpServer, Listen};
+
+#[cfg(all(test, feature = "ssl"))]
+use crate::server::test_ssl_server;
+
+#[cfg(test)]
+use crate::http_headers;
+
+/// Execute a GET request. Redirects are followed, up to `DEFAULT_MAX_REDIRECTS` of them.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn get<U: TryInto<Url>>(url: U) -> Result<HttpBody<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    get_with_max_redirects(url, DEFAULT_MAX_REDIRECTS)
+}
+
+/// Like `get`, but follow at most `max_redirects` redirects, see `request_with_max_redirects`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn get_with_max_redirects<U: TryInto<Url>>(
+    url: U,
+    max_redirects: usize,
+) -> Result<HttpBody<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    expect_ok(request_with_max_redirects(
+        HttpMethod::Get,
+        url,
+        io::empty(),
+        max_redirects,
+    )?)
+}
+
+/// Execute a DELETE request. Redirects are followed, up to `DEFAULT_MAX_REDIRECTS` of them.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn delete<U: TryInto<Url>>(url: U) -> Result<HttpBody<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    expect_ok(request(HttpMethod::Delete, url, io::empty())?)
+}
+
+#[cfg(test)]
+fn delete_test<B: io::Read>(requester: impl FnOnce(&str) -> Result<HttpBody<B>>) -> Result<()> {
+    use std::io::Read as _;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Delete,
+        expected_uri: "/thing".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "deleted".into(),
+        response_headers: Default::default(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let mut body = requester(format!("http://localhost:{}/thing", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "deleted");
+    Ok(())
+}
+
+#[test]
+fn delete_request() {
+    delete_test(|a| delete(a)).unwrap();
+}
+
+#[test]
+fn http_client_delete_request() {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    delete_test(|a| Ok(client.delete(a)?.finish()?.body)).unwrap();
+}
+
+/// A socket which records what is sent to it and responds with a canned response.
+#[cfg(test)]
+struct Recorder {
+    sent: Vec<u8>,
+    response: &'static [u8],
+}
+
+#[cfg(test)]
+impl io::Read for Recorder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.response.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl io::Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn delete_request_sends_empty_chunked_body() {
+    let mut recorder = Recorder {
+        sent: Vec::new(),
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    let response = HttpRequestBuilder::delete("http://localhost/thing")
+        .unwrap()
+        .send(&mut recorder)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+
+    let sent = std::str::from_utf8(&recorder.sent).unwrap();
+    assert!(sent.starts_with("DELETE /thing HTTP/1.1\r\n"), "{}", sent);
+    assert!(sent.contains("Transfer-Encoding: chunked\r\n"), "{}", sent);
+    assert!(sent.ends_with("\r\n\r\n0\r\n\r\n"), "{}", sent);
+}
+
+#[test]
+fn small_writes_are_coalesced_in_to_chunks() {
+    use std::io::Write as _;
+
+    let mut recorder = Recorder {
+        sent: Vec::new(),
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    let mut request = HttpRequestBuilder::put("http://localhost/thing")
+        .unwrap()
+        .send(&mut recorder)
+        .unwrap();
+    for b in b"hello world" {
+        request.write_all(&[*b]).unwrap();
+    }
+    request.flush().unwrap();
+    request.set_chunk_size(4);
+    request.write_all(b"abcdefghij").unwrap();
+    request.write_all(b"k").unwrap();
+    request.finish().unwrap();
+
+    let sent = std::str::from_utf8(&recorder.sent).unwrap();
+    let body = sent.split_once("\r\n\r\n").unwrap().1;
+    assert_eq!(
+        body,
+        "b\r\nhello world\r\n\
+         a\r\nabcdefghij\r\n\
+         1\r\nk\r\n\
+         0\r\n\r\n"
+    );
+}
+
+#[test]
+fn chunk_size_zero_sends_every_write() {
+    use std::io::Write as _;
+
+    let mut recorder = Recorder {
+        sent: Vec::new(),
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    let mut request = HttpRequestBuilder::put("http://localhost/thing")
+        .unwrap()
+        .send(&mut recorder)
+        .unwrap();
+    request.set_chunk_size(0);
+    request.write_all(b"a").unwrap();
+    request.write_all(b"b").unwrap();
+    request.finish().unwrap();
+
+    let sent = std::str::from_utf8(&recorder.sent).unwrap();
+    assert!(
+        sent.ends_with("\r\n\r\n1\r\na\r\n1\r\nb\r\n0\r\n\r\n"),
+        "{}",
+        sent
+    );
+}
+
+#[test]
+fn request_builder_body_sends_content_length() {
+    let mut recorder = Recorder {
+        sent: Vec::new(),
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    let response = HttpRequestBuilder::put("http://localhost/thing")
+        .unwrap()
+        .body("hello world")
+        .send(&mut recorder)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+
+    let sent = std::str::from_utf8(&recorder.sent).unwrap();
+    assert!(sent.starts_with("PUT /thing HTTP/1.1\r\n"), "{}", sent);
+    assert!(sent.contains("Content-Length: 11\r\n"), "{}", sent);
+    assert!(!sent.contains("Transfer-Encoding"), "{}", sent);
+    assert!(sent.ends_with("\r\n\r\nhello world"), "{}", sent);
+}
+
+#[test]
+fn request_builder_options_server() {
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    HttpRequestBuilder::options_server("http://localhost:8080/ignored?too")
+        .unwrap()
+        .send(&mut socket)
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    assert!(sent.starts_with("OPTIONS * HTTP/1.1\r\n"), "{}", sent);
+    assert!(sent.contains("\r\nHost: localhost:8080\r\n"), "{}", sent);
+}
+
+#[test]
+fn request_builder_trace() {
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n",
+    };
+    let response = HttpRequestBuilder::trace("http://localhost:8080/a?b")
+        .unwrap()
+        .send(&mut socket)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    assert!(sent.starts_with("TRACE /a?b HTTP/1.1\r\n"), "{}", sent);
+    assert!(sent.ends_with("\r\n\r\n"), "{}", sent);
+    assert!(!sent.contains("Content-Length"), "{}", sent);
+    assert!(!sent.contains("Transfer-Encoding"), "{}", sent);
+}
+
+#[test]
+fn connect_falls_back_to_later_addresses() {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+
+    // Nothing listens on the port of a dropped listener, so connecting to it is refused.
+    let unreachable = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let id = |addr: Vec<SocketAddr>| StreamId {
+        addr,
+        host: "localhost".into(),
+        secure: false,
+        tunnel: None,
+    };
+
+    <TcpStream as StreamConnector>::connect(id(vec![unreachable, listener.local_addr().unwrap()]))
+        .unwrap();
+    listener.accept().unwrap();
+
+    assert!(<TcpStream as StreamConnector>::connect(id(vec![unreachable])).is_err());
+    assert!(<TcpStream as StreamConnector>::connect(id(vec![])).is_err());
+}
+
+#[test]
+fn prefer_address_family() {
+    use std::net::{SocketAddr, TcpStream};
+
+    let v4a: SocketAddr = "127.0.0.1:80".parse().unwrap();
+    let v4b: SocketAddr = "127.0.0.2:80".parse().unwrap();
+    let v6: SocketAddr = "[::1]:80".parse().unwrap();
+    let mut id = StreamId {
+        addr: vec![v4a, v6, v4b],
+        host: "localhost".into(),
+        secure: false,
+        tunnel: None,
+    };
+
+    TcpStream::prefer_address_family(&mut id, AddressFamily::Any);
+    assert_eq!(id.addr, vec![v4a, v6, v4b]);
+    TcpStream::prefer_address_family(&mut id, AddressFamily::Ipv6);
+    assert_eq!(id.addr, vec![v6, v4a, v4b]);
+    TcpStream::prefer_address_family(&mut id, AddressFamily::Ipv4);
+    assert_eq!(id.addr, vec![v4a, v4b, v6]);
+}
+
+#[test]
+fn request_builder_without_default_headers() {
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    HttpRequestBuilder::get("http://localhost:8080/")
+        .unwrap()
+        .without_default_headers()
+        .add_header("User-Agent", "custom-agent")
+        .send(&mut socket)
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    assert!(sent.contains("Host: localhost:8080\r\n"), "{}", sent);
+    assert!(sent.contains("User-Agent: custom-agent\r\n"), "{}", sent);
+    assert!(!sent.contains("http_io"), "{}", sent);
+    assert!(!sent.contains("Accept"), "{}", sent);
+}
+
+#[test]
+fn request_builder_remove_header() {
+    let builder = HttpRequestBuilder::get("http://localhost:8080/")
+        .unwrap()
+        .remove_header("user-agent");
+    assert_eq!(builder.request.headers.get("User-Agent"), None);
+    assert_eq!(builder.request.headers.get("Accept"), Some("*/*"));
+    assert_eq!(builder.request.headers.get("Host"), Some("localhost:8080"));
+}
+
+#[test]
+fn request_builder_host_port() {
+    let host = |url| {
+        let builder = HttpRequestBuilder::get(url).unwrap();
+        builder.headers().get("Host").unwrap().to_string()
+    };
+    assert_eq!(host("http://example.com/"), "example.com");
+    assert_eq!(host("http://example.com:80/"), "example.com");
+    assert_eq!(host("https://example.com:443/"), "example.com");
+    assert_eq!(host("http://example.com:8080/"), "example.com:8080");
+    assert_eq!(host("https://example.com:80/"), "example.com:80");
+    assert_eq!(host("http://[::1]:8080/"), "[::1]:8080");
+}
+
+#[test]
+fn request_builder_headers() {
+    let builder = HttpRequestBuilder::get("http://example.com/")
+        .unwrap()
+        .add_header("X-Custom", "1");
+    let headers = builder.headers();
+    assert_eq!(headers.get("Host"), Some("example.com"));
+    assert_eq!(headers.get("User-Agent"), Some("http_io"));
+    assert_eq!(headers.get("x-custom"), Some("1"));
+
+    let builder = builder.without_default_headers();
+    assert_eq!(builder.headers().get("User-Agent"), None);
+    assert_eq!(builder.headers().get("Host"), Some("example.com"));
+}
+
+#[test]
+fn request_builder_multipart() {
+    let form = crate::multipart::MultipartBuilder::with_boundary("boundary1234")
+        .text("description", "two fields")
+        .file(
+            "upload",
+            "hello.txt",
+            "text/plain",
+            io::Cursor::new(b"hello world".to_vec()),
+        );
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+    };
+    HttpRequestBuilder::post("http://localhost/upload")
+        .unwrap()
+        .multipart(form)
+        .send(&mut socket)
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    let (head, body) = sent.split_once("\r\n\r\n").unwrap();
+    assert!(
+        head.contains("\r\nContent-Type: multipart/form-data; boundary=boundary1234"),
+        "{}",
+        head
+    );
+    assert!(head.contains("\r\nTransfer-Encoding: chunked"), "{}", head);
+
+    let mut decoded = String::new();
+    let mut chunked =
+        crate::protocol::HttpBody::new(Some("chunked"), None, io::BufReader::new(body.as_bytes()));
+    io::Read::read_to_string(&mut chunked, &mut decoded).unwrap();
+    assert_eq!(
+        decoded,
+        "--boundary1234\r\n\
+         Content-Disposition: form-data; name=\"description\"\r\n\
+         \r\n\
+         two fields\r\n\
+         --boundary1234\r\n\
+         Content-Disposition: form-data; name=\"upload\"; filename=\"hello.txt\"\r\n\
+         Content-Type: text/plain\r\n\
+         \r\n\
+         hello world\r\n\
+         --boundary1234--\r\n"
+    );
+}
+
+#[test]
+fn request_builder_http_1_0_buffers_body() {
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.0 200 OK\r\n\r\nread til close",
+    };
+    let mut request = HttpRequestBuilder::post("http://localhost/things")
+        .unwrap()
+        .version(1, 0)
+        .send(&mut socket)
+        .unwrap();
+    io::Write::write_all(&mut request, b"hello ").unwrap();
+    io::Write::write_all(&mut request, b"world").unwrap();
+    let response = request.finish().unwrap();
+    assert_eq!(response.into_string().unwrap(), "read til close");
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    assert!(sent.starts_with("POST /things HTTP/1.0\r\n"), "{}", sent);
+    assert!(!sent.contains("Transfer-Encoding"), "{}", sent);
+    assert!(sent.contains("Content-Length: 11\r\n"), "{}", sent);
+    assert!(sent.ends_with("\r\n\r\nhello world"), "{}", sent);
+}
+
+#[test]
+fn request_builder_http_1_0_without_body() {
+    let mut socket = Recorder {
+        sent: vec![],
+        response: b"HTTP/1.0 200 OK\r\n\r\n",
+    };
+    HttpRequestBuilder::get("http://localhost/")
+        .unwrap()
+        .version(1, 0)
+        .send(&mut socket)
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let sent = String::from_utf8(socket.sent).unwrap();
+    assert!(sent.starts_with("GET / HTTP/1.0\r\n"), "{}", sent);
+    assert!(sent.ends_with("\r\n\r\n"), "{}", sent);
+}
+
+#[cfg(test)]
+fn get_test<
+    L: Listen + Send + 'static,
+    T: HttpRequestHandler<L::Stream> + Send + 'static,
     B: io::Read,
 >(
     scheme: Scheme,
@@ -350,38 +1973,798 @@ fn get_test<
 ) -> Result<()> {
     use std::io::Read as _;
 
-    let (port, mut server) = server_factory(vec![ExpectedRequest {
-        expected_method: HttpMethod::Get,
+    let (port, mut server) = server_factory(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+        response_headers: Default::default(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let mut body = requester(format!("{}://localhost:{}/", scheme, port).as_ref())?;
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[test]
+fn get_request() {
+    get_test(Scheme::Http, test_server, |a| get(a)).unwrap();
+}
+
+#[test]
+fn request_returns_whole_response() {
+    use std::io::Read as _;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/things".into(),
+        expected_body: "a thing".into(),
+        response_status: HttpStatus::Created,
+        response_body: "created".into(),
+        response_headers: http_headers! { "Location" => "/things/1" },
+    }])
+    .unwrap();
+    let handle = std::thread::spawn(move || server.serve_one());
+    let url = format!("http://localhost:{}/things", port);
+    let mut response = request(HttpMethod::Post, &url[..], &b"a thing"[..]).unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.status, HttpStatus::Created);
+    assert_eq!(response.headers.get("location"), Some("/things/1"));
+
+    let mut body_str = String::new();
+    response.body.read_to_string(&mut body_str).unwrap();
+    assert_eq!(body_str, "created");
+}
+
+#[test]
+fn http_client_get_request() {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    get_test(Scheme::Http, test_server, |a| {
+        Ok(client.get(a)?.finish()?.body)
+    })
+    .unwrap();
+}
+
+#[test]
+fn http_client_reconnects_when_server_closes_connection() {
+    let response = |body: &str| ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: body.into(),
+        response_headers: Default::default(),
+    };
+    let (port, mut server) = test_server(vec![response("first"), response("second")]).unwrap();
+    let url = format!("http://localhost:{}/", port);
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+
+    // The server closes every connection after one request.
+    let handle = std::thread::spawn(move || {
+        server.serve_one().unwrap();
+        server
+    });
+    let body = client
+        .get(&url[..])
+        .unwrap()
+        .finish()
+        .unwrap()
+        .into_string();
+    assert_eq!(body.unwrap(), "first");
+    let mut server = handle.join().unwrap();
+
+    let handle = std::thread::spawn(move || server.serve_one());
+    let body = client
+        .get(&url[..])
+        .unwrap()
+        .finish()
+        .unwrap()
+        .into_string();
+    assert_eq!(body.unwrap(), "second");
+    handle.join().unwrap().unwrap();
+}
+
+/// Connects with TCP, but can't tell when the server has closed a stream kept open, so the client
+/// only finds out once it sends the next request on it.
+#[cfg(test)]
+struct UncheckedTcpStream;
+
+#[cfg(test)]
+impl StreamConnector for UncheckedTcpStream {
+    type Stream = StdTransport;
+    type StreamAddr = <std::net::TcpStream as StreamConnector>::StreamAddr;
+
+    fn connect(a: Self::StreamAddr) -> Result<Self::Stream> {
+        <std::net::TcpStream as StreamConnector>::connect(a)
+    }
+
+    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr> {
+        std::net::TcpStream::to_stream_addr(url)
+    }
+}
+
+#[test]
+fn http_client_resends_body_when_server_closes_connection() {
+    use std::io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+    let url = format!(
+        "http://localhost:{}/",
+        listener.local_addr().unwrap().port()
+    );
+    // The server answers one request on each connection without saying it will close it, then
+    // closes it.
+    let (closed, wait_closed) = std::sync::mpsc::channel();
+    let handle = std::thread::spawn(move || -> Result<Vec<String>> {
+        let mut bodies = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept()?;
+            let mut body = String::new();
+            HttpRequest::deserialize(io::BufReader::new(&mut stream))?
+                .body
+                .read_to_string(&mut body)?;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+            drop(stream);
+            closed.send(()).unwrap();
+            bodies.push(body);
+        }
+        Ok(bodies)
+    });
+
+    let mut client = HttpClient::<UncheckedTcpStream>::new();
+    let response = client.put_body(&url[..], &b"first"[..]).unwrap();
+    assert_eq!(response.into_string().unwrap(), "first");
+    wait_closed.recv().unwrap();
+
+    let response = client.put_body(&url[..], &b"second"[..]).unwrap();
+    assert_eq!(response.into_string().unwrap(), "second");
+    assert_eq!(handle.join().unwrap().unwrap(), ["first", "second"]);
+}
+
+#[test]
+fn http_client_read_timeout() {
+    // The listener never accepts, so the connection succeeds but no response ever comes.
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+
+    let mut client = HttpClient::<std::net::TcpStream>::with_timeouts(
+        Some(std::time::Duration::from_secs(5)),
+        Some(std::time::Duration::from_millis(100)),
+    );
+    let url = format!("http://localhost:{}/", port);
+    let err = client.get(&url[..]).unwrap().finish().err().unwrap();
+    assert!(matches!(err, Error::Timeout), "{:?}", err);
+}
+
+#[test]
+fn http_client_expect_continue_ignored() {
+    use std::io::{BufRead as _, Read as _, Write as _};
+
+    // The server never answers `Expect`, it just waits for the body.
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = server_socket.accept().unwrap();
+        let mut reader = std::io::BufReader::new(stream);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let mut body = [0; 8];
+        reader.read_exact(&mut body).unwrap();
+        let mut stream = reader.into_inner();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nstored")
+            .unwrap();
+        body
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let url = format!("http://localhost:{}/upload", port);
+    let builder = client
+        .request(HttpMethod::Put, &url[..])
+        .unwrap()
+        .add_header("Expect", "100-continue")
+        .body("the body");
+    let response = client.send(builder).unwrap().finish().unwrap();
+    assert_eq!(&handle.join().unwrap(), b"the body");
+
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "stored");
+}
+
+#[test]
+fn request_builder_basic_auth() {
+    let builder = HttpRequestBuilder::get("http://localhost/")
+        .unwrap()
+        .basic_auth("Aladdin", Some("open sesame"));
+    assert_eq!(
+        builder.request.headers.get("Authorization"),
+        Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+    );
+
+    let builder = HttpRequestBuilder::get("http://localhost/")
+        .unwrap()
+        .basic_auth("Aladdin", None);
+    assert_eq!(
+        builder.request.headers.get("Authorization"),
+        Some("Basic QWxhZGRpbjo=")
+    );
+    assert_eq!(
+        crate::protocol::parse_authorization(builder.request.headers.get("Authorization").unwrap()),
+        Some(crate::protocol::AuthScheme::Basic {
+            user: "Aladdin".into(),
+            pass: "".into()
+        })
+    );
+}
+
+#[test]
+fn http_client_default_headers() {
+    use crate::server::EchoHeaderHandler;
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+    let mut server = HttpServer::new(server_socket, EchoHeaderHandler);
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_bearer_token("t0k3n");
+    client.set_default_header("User-Agent", "custom-agent");
+    client.set_default_header("X-Echo", "default");
+
+    let url = format!("http://localhost:{}/", port);
+    let builder = client
+        .request(HttpMethod::Get, &url[..])
+        .unwrap()
+        .add_header("X-Echo", "override");
+    let response = client.send(builder).unwrap().finish().unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.get_header("Authorization"), Some("Bearer t0k3n"));
+    assert_eq!(response.get_header("User-Agent"), Some("custom-agent"));
+    assert_eq!(response.get_header("X-Echo"), Some("override"));
+}
+
+#[test]
+fn http_client_head_and_options() {
+    use std::io::Read as _;
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = crate::server::LendingHandler::new(b"hello world"[..].into());
+    let mut server = HttpServer::new(server_socket.try_clone().unwrap(), handler);
+    server.set_head_from_get(true);
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = server_socket.accept()?;
+        server.serve_connection(stream)
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let mut response = client.head(&url[..]).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.get_header("Content-Length"), Some("11"));
+    let mut body = vec![];
+    response.body.read_to_end(&mut body).unwrap();
+    assert!(body.is_empty());
+
+    // Nothing was left unread, so the connection can be used for the next request.
+    let mut response = client.options(&url[..]).unwrap();
+    assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+    response.body.drain().unwrap();
+    let response = client.get(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.into_string().unwrap(), "hello world");
+
+    drop(client);
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn no_content_response_does_not_block() {
+    use std::io::{Read as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let url = format!("http://{}/", server_socket.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = server_socket.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        // The stray Content-Length would leave the client waiting if it believed it.
+        stream
+            .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 5\r\n\r\n")
+            .unwrap();
+        // Keep the connection open until the client is done with it.
+        let _ = stream.read(&mut request);
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let response = client.get(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.status, HttpStatus::NoContent);
+    assert_eq!(response.into_string().unwrap(), "");
+    drop(client);
+    handle.join().unwrap();
+}
+
+/// Serve a single connection by writing `response` after reading the request, then closing it.
+#[cfg(test)]
+fn raw_response_server(response: &'static str) -> (String, std::thread::JoinHandle<()>) {
+    use std::io::{Read as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let url = format!("http://{}/", server_socket.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = server_socket.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    (url, handle)
+}
+
+#[test]
+fn http_client_ipv6_literal() {
+    use std::io::{Read as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("[::1]:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+    let url = format!("http://[::1]:{}/", port);
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = server_socket.accept().unwrap();
+        let mut request = [0; 1024];
+        let len = stream.read(&mut request).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi")
+            .unwrap();
+        String::from_utf8(request[..len].to_vec()).unwrap()
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let response = client.get(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.into_string().unwrap(), "hi");
+    let request = handle.join().unwrap();
+    let host = format!("\r\nHost: [::1]:{}\r\n", port);
+    assert!(request.contains(&host), "{}", request);
+}
+
+#[cfg(test)]
+fn connection_close_test(
+    get: impl Fn(&mut HttpClient<std::net::TcpStream>, &str) -> (bool, String),
+) {
+    use std::io::{Read as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let url = format!("http://{}/", server_socket.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let mut request = [0; 1024];
+        let (mut first, _) = server_socket.accept().unwrap();
+        let _ = first.read(&mut request).unwrap();
+        first
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\nfirst")
+            .unwrap();
+        // The first connection is left open, but the next request has to come on a new one.
+        let (mut second, _) = server_socket.accept().unwrap();
+        let _ = second.read(&mut request).unwrap();
+        second
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")
+            .unwrap();
+        // The client closed the first connection without sending anything more on it.
+        assert_eq!(first.read(&mut request).unwrap(), 0);
+    });
+
+    let mut client =
+        HttpClient::<std::net::TcpStream>::with_timeouts(None, Some(Duration::from_secs(1)));
+    assert_eq!(get(&mut client, &url), (false, "first".into()));
+    assert_eq!(get(&mut client, &url), (true, "second".into()));
+    handle.join().unwrap();
+}
+
+#[test]
+fn http_client_honors_connection_close() {
+    connection_close_test(|client, url| {
+        let response = client.get_following_redirects(url).unwrap();
+        (response.keep_alive(), response.into_string().unwrap())
+    });
+}
+
+#[test]
+fn http_client_honors_connection_close_after_finish() {
+    connection_close_test(|client, url| {
+        let response = client.get(url).unwrap().finish().unwrap();
+        (response.keep_alive(), response.into_string().unwrap())
+    });
+}
+
+#[test]
+fn http_client_max_response_bytes() {
+    max_response_bytes_test(|client, url| client.get_following_redirects(url));
+}
+
+#[test]
+fn http_client_max_response_bytes_after_finish() {
+    max_response_bytes_test(|client, url| client.get(url)?.finish());
+}
+
+#[cfg(test)]
+fn max_response_bytes_test(
+    send: impl for<'a> Fn(
+        &'a mut HttpClient<std::net::TcpStream>,
+        &str,
+    ) -> Result<HttpResponse<&'a mut StdTransport>>,
+) {
+    use std::io::Read as _;
+
+    let get = |response| {
+        let (url, handle) = raw_response_server(response);
+        let mut client = HttpClient::<std::net::TcpStream>::new();
+        client.set_max_response_bytes(Some(10));
+        let mut body = vec![];
+        let result = send(&mut client, &url).and_then(|mut response| {
+            let result = response.body.read_to_end(&mut body);
+            assert_eq!(response.body.exceeded_size_limit(), result.is_err());
+            Ok(result?)
+        });
+        handle.join().unwrap();
+        (result, body)
+    };
+
+    // Bodies up to the limit are read as usual.
+    let (result, body) = get("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n0123456789");
+    assert_eq!(result.unwrap(), 10);
+    assert_eq!(body, b"0123456789");
+
+    // A body without a length fails part way through.
+    let (result, body) = get("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n0123456789abcdef");
+    assert!(result.is_err());
+    assert!(body.len() <= 10);
+
+    // So does a chunked body.
+    let (result, body) = get("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+        8\r\n01234567\r\n8\r\n89abcdef\r\n0\r\n\r\n");
+    assert!(result.is_err());
+    assert_eq!(body, b"01234567");
+
+    // A body which says it is too long fails right away.
+    let (result, _) = get("HTTP/1.1 200 OK\r\nContent-Length: 16\r\n\r\n0123456789abcdef");
+    assert!(matches!(result, Err(Error::BodyTooLarge)));
+}
+
+/// Records everything logged by any `LoggingStream` using it or one of its clones.
+#[cfg(test)]
+#[derive(Default, Clone)]
+struct RecordingLogger(std::sync::Arc<std::sync::Mutex<Recorded>>);
+
+#[cfg(test)]
+type Recorded = Vec<(StreamDirection, Vec<u8>)>;
+
+#[cfg(test)]
+impl StreamLogger for RecordingLogger {
+    fn log(&mut self, direction: StreamDirection, bytes: &[u8]) {
+        self.0.lock().unwrap().push((direction, bytes.to_vec()));
+    }
+}
+
+#[test]
+fn http_client_logging_stream() {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/logged".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello".into(),
+        response_headers: Default::default(),
+    }])
+    .unwrap();
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://localhost:{}/logged", port);
+    let logger = RecordingLogger::default();
+    let mut client =
+        HttpClient::<LoggingStream<std::net::TcpStream, _>>::with_logger(logger.clone());
+    let response = client.get(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.into_string().unwrap(), "hello");
+    handle.join().unwrap().unwrap();
+
+    let recorded = logger.0.lock().unwrap();
+    let all = |direction| -> Vec<u8> {
+        recorded
+            .iter()
+            .filter(|(d, _)| *d == direction)
+            .flat_map(|(_, bytes)| bytes.clone())
+            .collect()
+    };
+    let written = String::from_utf8(all(StreamDirection::Write)).unwrap();
+    let read = String::from_utf8(all(StreamDirection::Read)).unwrap();
+    assert!(
+        written.starts_with("GET /logged HTTP/1.1\r\n"),
+        "{}",
+        written
+    );
+    assert!(read.starts_with("HTTP/1.1 200 OK\r\n"), "{}", read);
+    assert!(read.ends_with("hello"), "{}", read);
+}
+
+#[test]
+fn http_client_put_body() {
+    let (port, mut server) = test_server(vec![
+        ExpectedRequest {
+            expected_method: HttpMethod::Put,
+            expected_uri: "/upload".into(),
+            expected_body: "uploaded from a cursor".into(),
+            response_status: HttpStatus::OK,
+            response_body: "stored".into(),
+            response_headers: Default::default(),
+        },
+        ExpectedRequest {
+            expected_method: HttpMethod::Post,
+            expected_uri: "/upload".into(),
+            expected_body: "posted".into(),
+            response_status: HttpStatus::Created,
+            response_body: "".into(),
+            response_headers: Default::default(),
+        },
+    ])
+    .unwrap();
+    let handle = std::thread::spawn(move || {
+        server.serve_one()?;
+        server.serve_one()
+    });
+
+    let url = format!("http://localhost:{}/upload", port);
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let body = io::Cursor::new(b"uploaded from a cursor".to_vec());
+    let response = client.put_body(&url[..], body).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "stored");
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let response = client
+        .request_with_body(HttpMethod::Post, &url[..], &b"posted"[..])
+        .unwrap();
+    assert_eq!(response.status, HttpStatus::Created);
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    assert!(client
+        .request_with_body(HttpMethod::Get, &url[..], &b"no body allowed"[..])
+        .is_err());
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn http_client_request_with_body_rejected_keeps_connection_usable() {
+    use crate::server::Router;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut router = Router::new();
+    for (method, path) in [
+        (HttpMethod::Post, "/upload"),
+        (HttpMethod::Get, "/upload"),
+        (HttpMethod::Get, "/after"),
+    ] {
+        let sender = sender.clone();
+        router = router.route(method, path, move |_, _, _| {
+            sender.send(format!("{} {}", method, path)).unwrap();
+            Ok(HttpResponse::from_string(HttpStatus::OK, path))
+        });
+    }
+    drop(sender);
+    let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepting = listener.try_clone().unwrap();
+    let mut server = HttpServer::new(listener, router);
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = accepting.accept()?;
+        server.serve_connection(stream)
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::with_timeouts(
+        None,
+        Some(std::time::Duration::from_secs(5)),
+    );
+    let url = format!("http://localhost:{}", port);
+    let response = client
+        .request_with_body(
+            HttpMethod::Post,
+            &format!("{}/upload", url)[..],
+            &b"posted"[..],
+        )
+        .unwrap();
+    assert_eq!(response.into_string().unwrap(), "/upload");
+    assert!(client
+        .request_with_body(
+            HttpMethod::Get,
+            &format!("{}/upload", url)[..],
+            &b"no body allowed"[..]
+        )
+        .is_err());
+
+    // The same connection is used for the next request.
+    let response = client
+        .get(&format!("{}/after", url)[..])
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.into_string().unwrap(), "/after");
+    drop(client);
+    handle.join().unwrap().unwrap();
+
+    let requests: Vec<String> = receiver.iter().collect();
+    assert_eq!(requests, ["POST /upload", "GET /after"]);
+}
+
+#[test]
+fn request_builder_query() {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/search?x=1&q=a+b%26c&name=%C3%A9t%C3%A9".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+        response_headers: Default::default(),
+    }])
+    .unwrap();
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://localhost:{}/search?x=1", port);
+    let stream = std::net::TcpStream::connect(("localhost", port)).unwrap();
+    let response = HttpRequestBuilder::get(&url[..])
+        .unwrap()
+        .query([("q", "a b&c"), ("name", "été")])
+        .send(stream)
+        .unwrap()
+        .finish()
+        .unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.status, HttpStatus::OK);
+}
+
+#[test]
+fn form_request() {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/form".into(),
+        expected_body: "name=Jo%26Bob&note=&city=Z%C3%BCrich&a%20b=c%3Dd".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+        response_headers: Default::default(),
+    }])
+    .unwrap();
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://localhost:{}/form", port);
+    let stream = std::net::TcpStream::connect(("localhost", port)).unwrap();
+    let builder = HttpRequestBuilder::post(&url[..]).unwrap().form(vec![
+        ("name", "Jo&Bob"),
+        ("note", ""),
+        ("city", "Zürich"),
+        ("a b", "c=d"),
+    ]);
+    assert_eq!(
+        builder.request.headers.get("Content-Type"),
+        Some("application/x-www-form-urlencoded")
+    );
+    let response = builder.send(stream).unwrap().finish().unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.status, HttpStatus::OK);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_request_and_response() {
+    use std::collections::BTreeMap;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
         expected_uri: "/".into(),
-        expected_body: "".into(),
+        expected_body: "[1,2,3]".into(),
         response_status: HttpStatus::OK,
-        response_body: "hello from server".into(),
+        response_body: "{\"a\": 1, \"b\": 2}".into(),
         response_headers: Default::default(),
-    }])?;
+    }])
+    .unwrap();
     let handle = std::thread::spawn(move || server.serve_one());
-    let mut body = requester(format!("{}://localhost:{}/", scheme, port).as_ref())?;
-    handle.join().unwrap()?;
 
-    let mut body_str = String::new();
-    body.read_to_string(&mut body_str)?;
-    assert_eq!(body_str, "hello from server");
-    Ok(())
+    let url = format!("http://localhost:{}/", port);
+    let stream = std::net::TcpStream::connect(("localhost", port)).unwrap();
+    let mut response = HttpRequestBuilder::put(&url[..])
+        .unwrap()
+        .json(&vec![1, 2, 3])
+        .unwrap()
+        .send(stream)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let value: BTreeMap<String, u32> = response.json().unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(
+        value,
+        vec![("a".into(), 1), ("b".into(), 2)].into_iter().collect()
+    );
 }
 
+#[cfg(feature = "flate")]
 #[test]
-fn get_request() {
-    get_test(Scheme::Http, test_server, |a| get(a)).unwrap();
+fn http_client_decodes_gzip_response() {
+    use crate::server::{HttpServer, Router};
+    use std::io::Write as _;
+
+    let router = Router::new().route(HttpMethod::Get, "/", |_, headers, _| {
+        let accepted = headers.get("Accept-Encoding").unwrap_or("");
+        if !accepted.contains("gzip") {
+            return Ok(HttpResponse::from_string(HttpStatus::OK, "plain"));
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(b"compressed").unwrap();
+        let body = encoder.finish().unwrap();
+        let length = body.len() as u64;
+        let body: Box<dyn io::Read> = Box::new(io::Cursor::new(body));
+        let mut response = HttpResponse::new(HttpStatus::OK, body).with_length(length);
+        response.add_header("Content-Encoding", "gzip");
+        Ok(response)
+    });
+    let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut server = HttpServer::new(listener, router);
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_accept_compressed();
+    let url = format!("http://localhost:{}/", port);
+    let response = client
+        .get(&url[..])
+        .unwrap()
+        .finish()
+        .unwrap()
+        .decode(DECODABLE_ENCODINGS)
+        .unwrap();
+    assert_eq!(response.get_header("Content-Encoding"), None);
+    assert_eq!(response.into_string().unwrap(), "compressed");
+    handle.join().unwrap().unwrap();
 }
 
+#[cfg(feature = "flate")]
 #[test]
-fn http_client_get_request() {
+fn http_client_decodes_server_encoded_response() {
+    use crate::server::{HttpServer, Router};
+
+    let body = "compress me ".repeat(100);
+    let expected = body.clone();
+    let router = Router::new().route(HttpMethod::Get, "/", move |_, headers, _| {
+        let response = HttpResponse::from_string(HttpStatus::OK, body.clone());
+        Ok(response.encode(headers.get("Accept-Encoding")))
+    });
+    let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut server = HttpServer::new(listener, router);
+    let handle = std::thread::spawn(move || server.serve_one());
+
     let mut client = HttpClient::<std::net::TcpStream>::new();
-    get_test(Scheme::Http, test_server, |a| {
-        Ok(client.get(a)?.finish()?.body)
-    })
-    .unwrap();
+    client.set_accept_compressed();
+    let url = format!("http://localhost:{}/", port);
+    let response = client.get(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.get_header("Transfer-Encoding"), Some("chunked"));
+    let response = response.decode(DECODABLE_ENCODINGS).unwrap();
+    assert_eq!(response.into_string().unwrap(), expected);
+    handle.join().unwrap().unwrap();
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn get_request_ssl() {
     get_test(
@@ -392,6 +2775,7 @@ fn get_request_ssl() {
     .unwrap();
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn http_client_get_request_ssl() {
     let mut client = HttpClient::<std::net::TcpStream>::new();
@@ -403,7 +2787,72 @@ fn http_client_get_request_ssl() {
     .unwrap();
 }
 
-/// Execute a PUT request.
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_get_request_ssl_with_config() {
+    use crate::ssl::{SslClientConfig, TlsVersion};
+
+    // Tests always trust test_ca.pem, so this server's certificate is from another authority.
+    let server = |s| test_ssl_server("test_private_key.pem", "test_private_cert.pem", s);
+    let mut config = SslClientConfig::default();
+    config.min_version = Some(TlsVersion::Tls1_2);
+    config.alpn_protocols = vec!["http/1.1".into()];
+
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config.clone());
+    let err = get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap_err();
+    assert!(matches!(err, Error::SslError(_)), "{:?}", err);
+
+    let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    config.root_certificates =
+        vec![std::fs::read(manifest_dir.join("test_private_ca.pem")).unwrap()];
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap();
+}
+
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_rejects_too_long_alpn_protocol() {
+    use crate::ssl::SslClientConfig;
+
+    let server = |s| test_ssl_server("test_key.pem", "test_cert.pem", s);
+    let mut config = SslClientConfig::default();
+    config.alpn_protocols = vec!["a".repeat(256)];
+
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    let err = get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap_err();
+    assert!(matches!(err, Error::SslError(_)), "{:?}", err);
+}
+
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_peer_certificates() {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let mut certs = None;
+    get_test(
+        Scheme::Https,
+        |s| test_ssl_server("test_key.pem", "test_cert.pem", s),
+        |a| {
+            let mut body = client.get(a)?.finish()?.body;
+            match body.get_mut() {
+                Some(StreamEither::B(s)) => certs = s.peer_certificates(),
+                _ => panic!("expected a secure stream"),
+            }
+            Ok(body)
+        },
+    )
+    .unwrap();
+
+    // The server's certificate is first, and it is for the host connected to.
+    let cert = &certs.unwrap()[0];
+    assert_eq!(
+        cert.der[0], 0x30,
+        "a DER certificate starts with a SEQUENCE"
+    );
+    assert!(cert.der.windows(9).any(|w| w == b"localhost"));
+}
+
+/// Execute a PUT request. Redirects are followed, up to `DEFAULT_MAX_REDIRECTS` of them. A body
+/// larger than 64 KiB isn't kept to send again, so a redirect which keeps the method fails.
 ///
 /// *This function is available if http_io is built with the `"std"` feature.*
 #[cfg(feature = "std")]
@@ -411,11 +2860,186 @@ pub fn put<U: TryInto<Url>, R: io::Read>(url: U, body: R) -> Result<HttpBody<Std
 where
     <U as TryInto<Url>>::Error: Display,
 {
+    expect_ok(request(HttpMethod::Put, url, body)?)
+}
+
+/// Execute a GET request and write the response body to the file at `path`, returning the number
+/// of bytes written. The body is written to a temporary file next to `path` which only replaces
+/// it once the whole body has been read, so a failed download leaves any existing file as it was.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn download<U: TryInto<Url>>(url: U, path: impl AsRef<std::path::Path>) -> Result<u64>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let path = path.as_ref();
+    let mut body = get(url)?;
+    let mut temp_name = path
+        .file_name()
+        .ok_or_else(|| Error::Other(format!("{} is not a file path", path.display())))?
+        .to_os_string();
+    temp_name.push(format!(".{}.download", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = std::fs::File::create(&temp_path)?;
+    let result = io::copy(&mut body, &mut file)
+        .and_then(|written| file.sync_all().map(|()| written))
+        .and_then(|written| {
+            drop(file);
+            std::fs::rename(&temp_path, path).map(|()| written)
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+/// Execute a PUT request with the contents of the file at `path` as the body. Redirects are
+/// followed, up to `DEFAULT_MAX_REDIRECTS` of them. Like `request`, the whole response is returned
+/// whatever its status is.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn upload<U: TryInto<Url>>(
+    url: U,
+    path: impl AsRef<std::path::Path>,
+) -> Result<HttpResponse<StdTransport>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    use std::io::Seek as _;
+
     let url = url
         .try_into()
         .map_err(|e| Error::ParseError(e.to_string()))?;
-    let builder = HttpRequestBuilder::put(url.clone())?;
-    Ok(send_request(builder, url, body)?)
+    let file = std::fs::File::open(path)?;
+    // The file is streamed rather than kept in memory, and read again from the start if the
+    // request is redirected.
+    let rewind: fn(&mut std::fs::File) -> io::Result<()> = |file| file.rewind();
+    send_request(
+        HttpMethod::Put,
+        url,
+        file,
+        Some(rewind),
+        DEFAULT_MAX_REDIRECTS,
+    )
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("http_io_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn download_and_upload_files() {
+    let (port, mut server) = test_server(vec![
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/file".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "downloaded contents".into(),
+            response_headers: Default::default(),
+        },
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/missing".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::NotFound,
+            response_body: "".into(),
+            response_headers: Default::default(),
+        },
+        ExpectedRequest {
+            expected_method: HttpMethod::Put,
+            expected_uri: "/file".into(),
+            expected_body: "downloaded contents".into(),
+            response_status: HttpStatus::Created,
+            response_body: "".into(),
+            response_headers: Default::default(),
+        },
+    ])
+    .unwrap();
+    let handle = std::thread::spawn(move || {
+        for _ in 0..3 {
+            server.serve_one()?;
+        }
+        Ok::<_, Error>(())
+    });
+
+    let dir = test_dir("download_and_upload_files");
+    let path = dir.join("file");
+    let written = download(&format!("http://localhost:{}/file", port)[..], &path).unwrap();
+    assert_eq!(written, 19);
+    assert_eq!(std::fs::read(&path).unwrap(), b"downloaded contents");
+
+    let missing = dir.join("missing");
+    let error = download(&format!("http://localhost:{}/missing", port)[..], &missing);
+    assert!(matches!(
+        error,
+        Err(Error::UnexpectedStatus(HttpStatus::NotFound))
+    ));
+    assert!(!missing.exists());
+
+    let response = upload(&format!("http://localhost:{}/file", port)[..], &path).unwrap();
+    assert_eq!(response.status, HttpStatus::Created);
+
+    handle.join().unwrap().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn failed_download_keeps_existing_file() {
+    let (url, handle) =
+        raw_response_server("HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial");
+    let dir = test_dir("failed_download_keeps_existing_file");
+    let path = dir.join("file");
+    std::fs::write(&path, "old contents").unwrap();
+
+    assert!(download(&url[..], &path).is_err());
+    handle.join().unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"old contents");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn upload_large_file_redirected() {
+    let contents = "a".repeat(MAX_RECORDED_BODY + 1);
+    let (port, mut server) = test_server(vec![
+        redirect_request(
+            HttpMethod::Put,
+            "/",
+            &contents,
+            HttpStatus::TemporaryRedirect,
+            "/next",
+        ),
+        ExpectedRequest {
+            expected_method: HttpMethod::Put,
+            expected_uri: "/next".into(),
+            expected_body: contents.clone(),
+            response_status: HttpStatus::Created,
+            response_body: "".into(),
+            response_headers: Default::default(),
+        },
+    ])
+    .unwrap();
+    let handle = std::thread::spawn(move || {
+        for _ in 0..2 {
+            server.serve_one()?;
+        }
+        Ok::<_, Error>(())
+    });
+
+    let dir = test_dir("upload_large_file_redirected");
+    let path = dir.join("file");
+    std::fs::write(&path, &contents).unwrap();
+    let response = upload(&format!("http://localhost:{}/", port)[..], &path).unwrap();
+    assert_eq!(response.status, HttpStatus::Created);
+    handle.join().unwrap().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
 }
 
 #[cfg(test)]
@@ -478,6 +3102,7 @@ fn http_client_put_request() {
     .unwrap();
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn put_request_ssl() {
     put_test(
@@ -488,6 +3113,7 @@ fn put_request_ssl() {
     .unwrap();
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn http_client_put_request_ssl() {
     let mut client = HttpClient::<std::net::TcpStream>::new();
@@ -499,6 +3125,7 @@ fn http_client_put_request_ssl() {
     .unwrap();
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn get_ssl_success() {
     use std::io::Read as _;
@@ -511,6 +3138,7 @@ fn get_ssl_success() {
     }
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn get_ssl_failure() {
     let mut client = HttpClient::<std::net::TcpStream>::new();
@@ -519,6 +3147,7 @@ fn get_ssl_failure() {
     assert!(matches!(err, Error::SslError(_)));
 }
 
+#[cfg(all(test, feature = "ssl"))]
 #[test]
 fn get_ssl_bad_certificate_name() {
     // These certificates have a hostname different from localhost, so the hostname verification
@@ -532,37 +3161,354 @@ fn get_ssl_bad_certificate_name() {
     assert!(matches!(err, Error::SslError(_)));
 }
 
-#[ignore]
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_ssl_bad_certificate() {
+    use crate::ssl::SslClientConfig;
+
+    let server = |s| test_ssl_server("test_bad_key.pem", "test_bad_cert.pem", s);
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let err = get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap_err();
+    assert!(matches!(err, Error::SslError(_)));
+
+    let config = SslClientConfig::default().danger_accept_invalid_certs(true);
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap();
+}
+
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_ssl_pinned_public_key() {
+    use crate::ssl::SslClientConfig;
+
+    // The SHA-256 hash of the public key of test_cert.pem.
+    const PIN: [u8; 32] = [
+        0x03, 0xd4, 0x33, 0x15, 0x87, 0x9c, 0xc5, 0x8c, 0xb7, 0xaa, 0xc9, 0xa0, 0x68, 0xb4, 0x0c,
+        0x22, 0x44, 0x23, 0x9f, 0x99, 0xe7, 0x3d, 0x99, 0x57, 0x41, 0x76, 0xef, 0x6e, 0x8a, 0xd2,
+        0x5e, 0x07,
+    ];
+
+    let server = |s| test_ssl_server("test_key.pem", "test_cert.pem", s);
+    let mut config = SslClientConfig::default();
+    config.pinned_public_keys = vec![[0; 32], PIN];
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap();
+
+    let mut config = SslClientConfig::default();
+    config.pinned_public_keys = vec![[0; 32]];
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    let err = get_test(Scheme::Https, server, |a| Ok(client.get(a)?.finish()?.body)).unwrap_err();
+    assert!(matches!(err, Error::SslError(_)));
+}
+
+// native-tls can't ask clients for a certificate when acting as the server
+#[cfg(all(feature = "ssl", not(feature = "native-tls")))]
+#[test]
+fn http_client_ssl_client_certificate() {
+    use crate::server::test_ssl_server_with_client_auth;
+    use crate::ssl::{SslClientCertificate, SslClientConfig};
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let result = get_test(Scheme::Https, test_ssl_server_with_client_auth, |a| {
+        Ok(client.get(a)?.finish()?.body)
+    });
+    assert!(result.is_err());
+
+    let read = |name| std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(name));
+    let mut config = SslClientConfig::default();
+    config.client_certificate = Some(SslClientCertificate {
+        cert_chain_pem: read("test_cert.pem").unwrap(),
+        private_key_pem: read("test_key.pem").unwrap(),
+    });
+    let mut client = HttpClient::<std::net::TcpStream>::with_ssl_config(config);
+    get_test(Scheme::Https, test_ssl_server_with_client_auth, |a| {
+        Ok(client.get(a)?.finish()?.body)
+    })
+    .unwrap();
+}
+
+/// A proxy which handles one connection. `CONNECT` requests are tunneled to their target, other
+/// requests are answered with their request line.
+#[cfg(test)]
+fn test_proxy() -> Result<Url> {
+    use std::io::{BufRead as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let proxy_url = format!("http://localhost:{}", listener.local_addr()?.port());
+    std::thread::spawn(move || -> Result<()> {
+        let (mut client, _) = listener.accept()?;
+        let mut reader = std::io::BufReader::new(client.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+
+        let mut parts = request_line.split(' ');
+        if parts.next() == Some("CONNECT") {
+            let mut target = std::net::TcpStream::connect(parts.next().unwrap())?;
+            client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+            let (mut client_read, mut target_write) = (client.try_clone()?, target.try_clone()?);
+            std::thread::spawn(move || std::io::copy(&mut client_read, &mut target_write));
+            std::io::copy(&mut target, &mut client)?;
+        } else {
+            let body = request_line.trim_end();
+            write!(
+                client,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+        }
+        Ok(())
+    });
+    Ok(proxy_url.parse()?)
+}
+
+#[test]
+fn http_client_proxy_http() {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_proxy(Some(test_proxy().unwrap()));
+    let response = client
+        .get("http://example.invalid/path?a=b#fragment")
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(
+        response.into_string().unwrap(),
+        "GET http://example.invalid/path?a=b HTTP/1.1"
+    );
+}
+
+#[cfg(all(test, feature = "ssl"))]
+#[test]
+fn http_client_proxy_https() {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_proxy(Some(test_proxy().unwrap()));
+    get_test(
+        Scheme::Https,
+        |s| test_ssl_server("test_key.pem", "test_cert.pem", s),
+        |a| Ok(client.get(a)?.finish()?.body),
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+fn redirect_request(
+    method: HttpMethod,
+    uri: &str,
+    body: &str,
+    status: HttpStatus,
+    location: &str,
+) -> ExpectedRequest {
+    ExpectedRequest {
+        expected_method: method,
+        expected_uri: uri.into(),
+        expected_body: body.into(),
+        response_status: status,
+        response_body: "".into(),
+        response_headers: http_headers! {
+            "Location" => location
+        },
+    }
+}
+
+#[cfg(test)]
+fn final_request(method: HttpMethod, uri: &str, body: &str) -> ExpectedRequest {
+    ExpectedRequest {
+        expected_method: method,
+        expected_uri: uri.into(),
+        expected_body: body.into(),
+        response_status: HttpStatus::OK,
+        response_body: "real content".into(),
+        response_headers: Default::default(),
+    }
+}
+
+#[cfg(test)]
+fn redirect_test(
+    script: Vec<ExpectedRequest>,
+    requester: impl FnOnce(&str) -> Result<HttpBody<StdTransport>>,
+) -> Result<String> {
+    use std::io::Read as _;
+
+    let num_requests = script.len();
+    let (port, mut server) = test_server(script)?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        for _ in 0..num_requests {
+            server.serve_one()?;
+        }
+        Ok(())
+    });
+    let body = requester(format!("http://localhost:{}/", port).as_ref());
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    body?.read_to_string(&mut body_str)?;
+    Ok(body_str)
+}
+
 #[test]
 fn redirect() {
+    let body = redirect_test(
+        vec![
+            redirect_request(
+                HttpMethod::Get,
+                "/",
+                "",
+                HttpStatus::MovedPermanently,
+                "/next",
+            ),
+            final_request(HttpMethod::Get, "/next", ""),
+        ],
+        |a| get(a),
+    )
+    .unwrap();
+    assert_eq!(body, "real content");
+}
+
+#[test]
+fn redirect_see_other_becomes_get() {
+    let body = redirect_test(
+        vec![
+            redirect_request(HttpMethod::Put, "/", "data", HttpStatus::SeeOther, "/next"),
+            final_request(HttpMethod::Get, "/next", ""),
+        ],
+        |a| put(a, "data".as_bytes()),
+    )
+    .unwrap();
+    assert_eq!(body, "real content");
+}
+
+#[test]
+fn redirect_temporary_keeps_method_and_body() {
+    let body = redirect_test(
+        vec![
+            redirect_request(
+                HttpMethod::Put,
+                "/",
+                "data",
+                HttpStatus::TemporaryRedirect,
+                "a",
+            ),
+            redirect_request(
+                HttpMethod::Put,
+                "/a",
+                "data",
+                HttpStatus::PermanentRedirect,
+                "/b",
+            ),
+            final_request(HttpMethod::Put, "/b", "data"),
+        ],
+        |a| put(a, "data".as_bytes()),
+    )
+    .unwrap();
+    assert_eq!(body, "real content");
+}
+
+#[test]
+fn redirect_temporary_with_large_body() {
+    let data = "a".repeat(MAX_RECORDED_BODY + 1);
+    let err = redirect_test(
+        vec![redirect_request(
+            HttpMethod::Put,
+            "/",
+            &data,
+            HttpStatus::TemporaryRedirect,
+            "/next",
+        )],
+        |a| put(a, data.as_bytes()),
+    )
+    .unwrap_err();
+    assert!(matches!(err, Error::Other(_)), "{:?}", err);
+}
+
+#[test]
+fn too_many_redirects() {
+    let script = (0..=DEFAULT_MAX_REDIRECTS)
+        .map(|_| redirect_request(HttpMethod::Get, "/", "", HttpStatus::Found, "/"))
+        .collect();
+    let err = redirect_test(script, |a| get(a)).unwrap_err();
+    assert!(matches!(err, Error::TooManyRedirects));
+}
+
+#[test]
+fn too_many_redirects_with_lower_max() {
+    const MAX_REDIRECTS: usize = 2;
+    let script = (0..=MAX_REDIRECTS)
+        .map(|_| redirect_request(HttpMethod::Get, "/", "", HttpStatus::Found, "/"))
+        .collect();
+    let err = redirect_test(script, |a| get_with_max_redirects(a, MAX_REDIRECTS)).unwrap_err();
+    assert!(matches!(err, Error::TooManyRedirects), "{:?}", err);
+}
+
+#[test]
+fn http_client_redirect_to_other_host() {
     use std::io::Read as _;
 
-    let (port, mut server) = test_server(vec![
-        ExpectedRequest {
-            expected_method: HttpMethod::Get,
-            expected_uri: "/".into(),
-            expected_body: "".into(),
-            response_status: HttpStatus::MovedPermanently,
-            response_body: "".into(),
-            response_headers: http_headers! {
-                "Location" => "/next"
-            },
-        },
-        ExpectedRequest {
-            expected_method: HttpMethod::Get,
-            expected_uri: "/next".into(),
-            expected_body: "".into(),
-            response_status: HttpStatus::OK,
-            response_body: "real content".into(),
-            response_headers: Default::default(),
-        },
-    ])
+    let (port2, mut server2) =
+        test_server(vec![final_request(HttpMethod::Get, "/next", "")]).unwrap();
+    let location = format!("http://localhost:{}/next", port2);
+    let (port1, mut server1) = test_server(vec![redirect_request(
+        HttpMethod::Get,
+        "/",
+        "",
+        HttpStatus::TemporaryRedirect,
+        &location,
+    )])
     .unwrap();
+    let handle1 = std::thread::spawn(move || server1.serve_one());
+    let handle2 = std::thread::spawn(move || server2.serve_one());
 
-    let handle = std::thread::spawn(move || server.serve_one());
-    let mut body = get(format!("http://localhost:{}/", port).as_ref()).unwrap();
-    handle.join().unwrap().unwrap();
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    let url = format!("http://localhost:{}/", port1);
+    let mut response = client.get_following_redirects(&url[..]).unwrap();
+    handle1.join().unwrap().unwrap();
+    handle2.join().unwrap().unwrap();
 
+    assert_eq!(response.status, HttpStatus::OK);
     let mut body_str = String::new();
-    body.read_to_string(&mut body_str).unwrap();
+    response.body.read_to_string(&mut body_str).unwrap();
+    assert_eq!(body_str, "real content");
+}
+
+#[test]
+fn http_client_redirect_to_other_host_drops_credentials() {
+    use crate::server::Router;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let router = Router::new().route(HttpMethod::Get, "/next", move |_, headers, _| {
+        let credentials = ["Authorization", "Cookie"].map(|h| headers.get(h).map(String::from));
+        sender.send(credentials).unwrap();
+        Ok(HttpResponse::from_string(HttpStatus::OK, "other"))
+    });
+    let listener2 = std::net::TcpListener::bind("localhost:0").unwrap();
+    let location = format!(
+        "http://localhost:{}/next",
+        listener2.local_addr().unwrap().port()
+    );
+    let mut server2 = HttpServer::new(listener2, router);
+    let (port1, mut server1) = test_server(vec![redirect_request(
+        HttpMethod::Get,
+        "/",
+        "",
+        HttpStatus::Found,
+        &location,
+    )])
+    .unwrap();
+    let handle1 = std::thread::spawn(move || server1.serve_one());
+    let handle2 = std::thread::spawn(move || server2.serve_one());
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_bearer_token("secret");
+    client.set_default_header("Cookie", "session=secret");
+    let url = format!("http://localhost:{}/", port1);
+    let response = client.get_following_redirects(&url[..]).unwrap();
+    assert_eq!(response.into_string().unwrap(), "other");
+    handle1.join().unwrap().unwrap();
+    handle2.join().unwrap().unwrap();
+
+    assert_eq!(receiver.recv().unwrap(), [None, None]);
 }
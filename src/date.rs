@@ -0,0 +1,246 @@
+//! Formatting and parsing the dates used in headers like `Date` and `Last-Modified`, see RFC 7231
+//! section 7.1.1.1.
+//!
+//! # Example
+//! ```rust
+//! use http_io::date::{format_http_date, parse_http_date};
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! # fn main() -> http_io::error::Result<()> {
+//! let time = UNIX_EPOCH + Duration::from_secs(784111777);
+//! assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+//! assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT")?, time);
+//! # Ok(())
+//! # }
+//! ```
+use crate::error::{Error, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const LONG_DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The number of days from 1970-01-01 to the given date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Count years from March, so the leap day is the last day of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day which is the given number of days from 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = ((month_from_march + 2) % 12 + 1) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format the time as an IMF-fixdate, like `Sun, 06 Nov 1994 08:49:37 GMT`. This is the format
+/// senders must use, any fraction of a second is dropped.
+pub fn format_http_date(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => {
+            let before = e.duration();
+            -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let seconds = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday.
+    let weekday = DAY_NAMES[(days + 3).rem_euclid(7) as usize];
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        weekday,
+        day,
+        MONTH_NAMES[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parse a number made of between `min_digits` and `max_digits` ASCII digits.
+fn parse_number(s: &str, min_digits: usize, max_digits: usize) -> Option<u32> {
+    if s.len() < min_digits || s.len() > max_digits || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parse a time of day like `08:49:37` in to the number of seconds since midnight.
+fn parse_time_of_day(s: &str) -> Option<i64> {
+    let mut parts = s.split(':').map(|part| parse_number(part, 2, 2));
+    let (hour, minute, second) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(hour)), Some(Some(minute)), Some(Some(second)), None) => (hour, minute, second),
+        _ => return None,
+    };
+    // A second of 60 is allowed for leap seconds.
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(i64::from(hour) * 3600 + i64::from(minute) * 60 + i64::from(second))
+}
+
+/// Whether the field is one of the given names of days followed by a comma.
+fn is_day_with_comma(field: &str, day_names: &[&str]) -> bool {
+    matches!(field.strip_suffix(','), Some(day) if day_names.contains(&day))
+}
+
+fn parse_month(s: &str) -> Option<u32> {
+    MONTH_NAMES
+        .iter()
+        .position(|&m| m == s)
+        .map(|index| index as u32 + 1)
+}
+
+/// Parse a date in any of the three formats HTTP allows: IMF-fixdate (`Sun, 06 Nov 1994 08:49:37
+/// GMT`), the obsolete RFC 850 format (`Sunday, 06-Nov-94 08:49:37 GMT`) and the obsolete asctime
+/// format (`Sun Nov  6 08:49:37 1994`). Two digit years of the RFC 850 format are taken to be
+/// from 1970 to 2069. The day of the week isn't checked against the date.
+pub fn parse_http_date(s: &str) -> Result<SystemTime> {
+    let invalid = || Error::ParseError(format!("invalid HTTP date: {:?}", s));
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    let (year, month, day, time) = match fields[..] {
+        [weekday, day, month, year, time, "GMT"] if is_day_with_comma(weekday, &DAY_NAMES) => (
+            parse_number(year, 4, 4).map(i64::from),
+            parse_month(month),
+            parse_number(day, 2, 2),
+            time,
+        ),
+        [weekday, date, time, "GMT"] if is_day_with_comma(weekday, &LONG_DAY_NAMES) => {
+            let mut parts = date.split('-');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(day), Some(month), Some(year), None) => (
+                    parse_number(year, 2, 2)
+                        .map(|year| i64::from(year) + if year < 70 { 2000 } else { 1900 }),
+                    parse_month(month),
+                    parse_number(day, 2, 2),
+                    time,
+                ),
+                _ => return Err(invalid()),
+            }
+        }
+        [weekday, month, day, time, year] if DAY_NAMES.contains(&weekday) => (
+            parse_number(year, 4, 4).map(i64::from),
+            parse_month(month),
+            parse_number(day, 1, 2),
+            time,
+        ),
+        _ => return Err(invalid()),
+    };
+    let (year, month, day, time) = match (year, month, day, parse_time_of_day(time)) {
+        (Some(year), Some(month), Some(day), Some(time)) => (year, month, day, time),
+        _ => return Err(invalid()),
+    };
+
+    // Catch days past the end of the month by checking the date survives the round trip.
+    let days = days_from_civil(year, month, day);
+    if day == 0 || civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+    let seconds = days * SECONDS_PER_DAY + time;
+    Ok(if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    })
+}
+
+#[cfg(test)]
+mod date_tests {
+    use super::{format_http_date, parse_http_date};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn imf_fixdate_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap(),
+            time
+        );
+
+        for &seconds in &[0, 951782400, 1709251199, 4107542399] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(parse_http_date(&format_http_date(time)).unwrap(), time);
+        }
+    }
+
+    #[test]
+    fn rfc_850_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(
+            parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").unwrap(),
+            time
+        );
+        let time = parse_http_date("Thursday, 29-Feb-24 23:59:59 GMT").unwrap();
+        assert_eq!(format_http_date(time), "Thu, 29 Feb 2024 23:59:59 GMT");
+    }
+
+    #[test]
+    fn asctime_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994").unwrap(), time);
+        let time = parse_http_date("Thu Jan  1 00:00:00 1970").unwrap();
+        assert_eq!(format_http_date(time), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn before_the_epoch() {
+        let time = UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(format_http_date(time), "Wed, 31 Dec 1969 23:59:58 GMT");
+        assert_eq!(
+            parse_http_date("Wed, 31 Dec 1969 23:59:58 GMT").unwrap(),
+            UNIX_EPOCH - Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for date in &[
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 29 Feb 1900 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov +994 08:49:37 GMT",
+            "Sunday, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06-Nov-94 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sun Nov 06 08:49:37 94",
+            "Sun Foo  6 08:49:37 1994",
+        ] {
+            assert!(parse_http_date(date).is_err(), "{:?}", date);
+        }
+    }
+}
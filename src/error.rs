@@ -15,7 +15,13 @@ pub enum Error {
     UnexpectedStatus(HttpStatus),
     UnexpectedMethod(HttpMethod),
     UrlError(String),
+    /// A request the server must refuse to handle, for the given reason.
+    BadRequest(String),
     LengthRequired,
+    HeadersTooLarge,
+    BodyTooLarge,
+    TooManyRedirects,
+    Timeout,
     Other(String),
 
     #[cfg(feature = "std")]
@@ -25,16 +31,107 @@ pub enum Error {
     #[cfg(feature = "ssl")]
     /// *This variant is available if http_io is built with the `"ssl"` feature.*
     SslError(crate::ssl::Error),
+
+    #[cfg(feature = "json")]
+    /// *This variant is available if http_io is built with the `"json"` feature.*
+    JsonError(serde_json::Error),
 }
 
 pub type Result<R> = core::result::Result<R, Error>;
 
 #[cfg(feature = "std")]
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ParseIntError(e) => Some(e),
+            Error::Utf8Error(e) => Some(e),
+            Error::IoError(e) => Some(e),
+            #[cfg(feature = "ssl")]
+            Error::SslError(e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::JsonError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::ParseError(s) => write!(f, "parse error: {}", s),
+            Error::ParseIntError(e) => write!(f, "invalid number: {}", e),
+            Error::Utf8Error(e) => write!(f, "invalid UTF-8: {}", e),
+            Error::UnexpectedScheme(s) => write!(f, "unexpected scheme: {}", s),
+            Error::UnexpectedEof(s) => write!(f, "unexpected end of stream: {}", s),
+            Error::UnexpectedStatus(s) => write!(f, "unexpected status: {}", s),
+            Error::UnexpectedMethod(m) => write!(f, "unexpected method: {}", m),
+            Error::UrlError(s) => write!(f, "invalid URL: {}", s),
+            Error::BadRequest(s) => write!(f, "bad request: {}", s),
+            Error::LengthRequired => write!(f, "length required"),
+            Error::HeadersTooLarge => write!(f, "headers too large"),
+            Error::BodyTooLarge => write!(f, "body too large"),
+            Error::TooManyRedirects => write!(f, "too many redirects"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::Other(s) => write!(f, "{}", s),
+            #[cfg(feature = "std")]
+            Error::IoError(e) => write!(f, "I/O error: {}", e),
+            #[cfg(feature = "ssl")]
+            Error::SslError(e) => write!(f, "SSL error: {}", e),
+            #[cfg(feature = "json")]
+            Error::JsonError(e) => write!(f, "JSON error: {}", e),
+        }
+    }
+}
+
+#[test]
+fn display() {
+    assert_eq!(
+        Error::ParseError("Expected ': '".into()).to_string(),
+        "parse error: Expected ': '"
+    );
+    assert_eq!(
+        Error::UnexpectedStatus(HttpStatus::NotFound).to_string(),
+        "unexpected status: 404 Not Found"
+    );
+    assert_eq!(Error::TooManyRedirects.to_string(), "too many redirects");
+}
+
+#[test]
+fn io_error_display_and_source() {
+    use std::error::Error as _;
+
+    let error = Error::IoError(std::io::Error::new(
+        std::io::ErrorKind::ConnectionReset,
+        "connection reset",
+    ));
+    assert_eq!(error.to_string(), "I/O error: connection reset");
+    assert_eq!(error.source().unwrap().to_string(), "connection reset");
+    assert!(Error::Timeout.source().is_none());
+}
+
+#[test]
+fn io_error_would_block_round_trip() {
+    use std::io::ErrorKind;
+
+    // A non-blocking socket which isn't ready is not a timeout.
+    let error = Error::from(std::io::Error::from(ErrorKind::WouldBlock));
+    assert!(matches!(&error, Error::IoError(e) if e.kind() == ErrorKind::WouldBlock));
+    assert_eq!(std::io::Error::from(error).kind(), ErrorKind::WouldBlock);
+
+    let error = Error::from(std::io::Error::from(ErrorKind::TimedOut));
+    assert!(matches!(error, Error::Timeout));
+    assert_eq!(std::io::Error::from(error).kind(), ErrorKind::TimedOut);
+}
+
+impl Error {
+    /// Blocking sockets with a read timeout report it as `WouldBlock` on some platforms. For an
+    /// error from reading such a socket, turn that in to `Timeout`.
+    pub(crate) fn read_timed_out(self) -> Self {
+        match self {
+            #[cfg(feature = "std")]
+            Error::IoError(e) if e.kind() == std::io::ErrorKind::WouldBlock => Error::Timeout,
+            e => e,
+        }
     }
 }
 
@@ -47,7 +144,11 @@ impl From<str::Utf8Error> for Error {
 #[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::IoError(e)
+        match e.kind() {
+            std::io::ErrorKind::TimedOut => Error::Timeout,
+            std::io::ErrorKind::UnexpectedEof => Error::UnexpectedEof(e.to_string()),
+            _ => Error::IoError(e),
+        }
     }
 }
 
@@ -60,17 +161,18 @@ impl From<num::ParseIntError> for Error {
 #[cfg(feature = "std")]
 impl<W> From<std::io::IntoInnerError<W>> for Error {
     fn from(e: std::io::IntoInnerError<W>) -> Self {
-        Error::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("{}", e.error()),
-        ))
+        Error::IoError(std::io::Error::other(format!("{}", e.error())))
     }
 }
 
 #[cfg(feature = "std")]
 impl From<Error> for std::io::Error {
     fn from(e: Error) -> Self {
-        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+        match e {
+            Error::Timeout => std::io::ErrorKind::TimedOut.into(),
+            Error::IoError(e) => e,
+            e => std::io::Error::other(e.to_string()),
+        }
     }
 }
 
@@ -81,6 +183,13 @@ impl From<crate::ssl::Error> for Error {
     }
 }
 
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonError(e)
+    }
+}
+
 impl From<url::ParseError> for Error {
     fn from(e: url::ParseError) -> Self {
         Self::ParseError(e.to_string())
@@ -1,6 +1,8 @@
 //! This module provides re-implementations of things from std::io for building without std
 
 pub use crate::error::{Error, Result};
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::cmp;
 
 pub trait Read {
@@ -9,10 +11,7 @@ pub trait Read {
     where
         Self: Sized,
     {
-        Take {
-            inner: self,
-            limit: limit,
-        }
+        Take { inner: self, limit }
     }
     fn bytes(self) -> Bytes<Self>
     where
@@ -20,6 +19,16 @@ pub trait Read {
     {
         Bytes { inner: self }
     }
+    fn chain<R: Read>(self, next: R) -> Chain<Self, R>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+            done_first: false,
+        }
+    }
 
     fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
         while !buf.is_empty() {
@@ -38,6 +47,24 @@ pub trait Read {
             Ok(())
         }
     }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0u8; DEFAULT_BUF_SIZE];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                n => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let n = self.read_to_end(&mut bytes)?;
+        buf.push_str(core::str::from_utf8(&bytes)?);
+        Ok(n)
+    }
 }
 
 impl<T: Read + ?Sized> Read for &mut T {
@@ -52,6 +79,25 @@ impl<T: Read + ?Sized> Read for alloc::boxed::Box<T> {
     }
 }
 
+/// Where to seek to, like `std::io::SeekFrom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+pub trait Seek {
+    /// Seek to the given position, returning the new position from the start.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+}
+
+impl<T: Seek + ?Sized> Seek for &mut T {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
 pub trait Write {
     fn write(&mut self, buf: &[u8]) -> Result<usize>;
     fn flush(&mut self) -> Result<()>;
@@ -112,6 +158,17 @@ impl<T: Write + ?Sized> Write for &mut T {
     }
 }
 
+impl Write for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
 impl<T: Write + ?Sized> Write for alloc::boxed::Box<T> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         (**self).write(buf)
@@ -122,56 +179,213 @@ impl<T: Write + ?Sized> Write for alloc::boxed::Box<T> {
     }
 }
 
+/// Collects small writes in to a buffer, writing them to the inner writer once it fills up or is
+/// flushed. Unlike `std::io::BufWriter`, anything still buffered when it is dropped is lost, so it
+/// should be flushed or turned back in to the inner writer with `into_inner`.
 pub struct BufWriter<T> {
     inner: T,
+    buf: Vec<u8>,
+    capacity: usize,
 }
 
 impl<T> BufWriter<T> {
     pub fn new(inner: T) -> Self {
-        Self { inner }
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
     }
 
-    pub fn into_inner(self) -> Result<T> {
+    pub fn with_capacity(capacity: usize, inner: T) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Write> BufWriter<T> {
+    /// Write out what is buffered, returning the inner writer.
+    pub fn into_inner(mut self) -> Result<T> {
+        self.flush_buf()?;
         Ok(self.inner)
     }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.buf.len() {
+                break Ok(());
+            }
+            match self.inner.write(&self.buf[written..]) {
+                Ok(0) => break Err(Error::UnexpectedEof("failed to write buffered data".into())),
+                Ok(n) => written += n,
+                Err(e) => break Err(e),
+            }
+        };
+        // Whatever was written is gone from the buffer, even if writing the rest failed.
+        self.buf.drain(..written);
+        result
+    }
 }
 
 impl<T: Write> Write for BufWriter<T> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.inner.write(buf)
+        if self.buf.len() + buf.len() > self.capacity {
+            self.flush_buf()?;
+        }
+        if buf.len() >= self.capacity {
+            self.inner.write(buf)
+        } else {
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
     }
 
     fn flush(&mut self) -> Result<()> {
+        self.flush_buf()?;
         self.inner.flush()
     }
 }
 
+/// A reader with an internal buffer, like `std::io::BufReader`.
+pub trait BufRead: Read {
+    /// Return what is buffered, reading more from the inner reader if nothing is. It is only
+    /// empty at the end of the stream.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Mark `amt` bytes returned by `fill_buf` as read.
+    fn consume(&mut self, amt: usize);
+
+    /// Read bytes in to `buf` until `byte` or the end of the stream is reached. The delimiter is
+    /// included if it was found. Returns the number of bytes read.
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut read = 0;
+        loop {
+            let (done, used) = {
+                let available = self.fill_buf()?;
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..=i]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            self.consume(used);
+            read += used;
+            if done {
+                return Ok(read);
+            }
+        }
+    }
+
+    /// Read bytes up to and including the next `\n` and append them to `buf`. Fails if they
+    /// aren't valid UTF-8, in which case nothing is appended.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let read = self.read_until(b'\n', &mut bytes)?;
+        buf.push_str(core::str::from_utf8(&bytes)?);
+        Ok(read)
+    }
+}
+
+impl<T: BufRead + ?Sized> BufRead for &mut T {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        (**self).fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        (**self).consume(amt)
+    }
+}
+
+/// Reads from the inner reader in large chunks, handing them out from a buffer. Like
+/// `std::io::BufReader`, anything still buffered is lost when it is turned back in to the inner
+/// reader with `into_inner`.
 pub struct BufReader<T> {
     inner: T,
+    buf: Vec<u8>,
+    capacity: usize,
+    pos: usize,
+    filled: usize,
 }
 
 impl<T> BufReader<T> {
     pub fn new(inner: T) -> Self {
-        Self { inner }
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: T) -> Self {
+        Self {
+            inner,
+            // The buffer is only allocated once it is needed, many readers are never read from.
+            buf: Vec::new(),
+            capacity,
+            pos: 0,
+            filled: 0,
+        }
     }
 
     pub fn into_inner(self) -> T {
         self.inner
     }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get at the inner reader. Reading from it directly skips over whatever is buffered.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// What has been read from the inner reader but not yet from this one.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
 }
 
 impl<T: Read> Read for BufReader<T> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.inner.read(buf)
+        // Large reads skip the buffer when it is empty, there is no point copying them twice.
+        if self.pos == self.filled && buf.len() >= self.capacity {
+            return self.inner.read(buf);
+        }
+        let read = Read::read(&mut self.fill_buf()?, buf)?;
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<T: Read> BufRead for BufReader<T> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            if self.buf.len() < self.capacity {
+                self.buf.resize(self.capacity, 0);
+            }
+            self.filled = self.inner.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+        Ok(self.buffer())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.filled);
     }
 }
 
 const DEFAULT_BUF_SIZE: usize = 8 * 1024;
 
-pub fn copy<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W) -> Result<u64>
+pub fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
 where
-    R: Read,
-    W: Write,
+    R: Read + ?Sized,
+    W: Write + ?Sized,
 {
     let mut buf = [0u8; DEFAULT_BUF_SIZE];
     let mut written = 0;
@@ -204,6 +418,10 @@ impl<T> Take<T> {
         self.inner
     }
 
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
     pub fn limit(&self) -> u64 {
         self.limit
     }
@@ -222,6 +440,31 @@ impl<T: Read> Read for Take<T> {
     }
 }
 
+/// Reads everything from the first reader, then everything from the second.
+pub struct Chain<T, U> {
+    first: T,
+    second: U,
+    done_first: bool,
+}
+
+impl<T, U> Chain<T, U> {
+    pub fn into_inner(self) -> (T, U) {
+        (self.first, self.second)
+    }
+}
+
+impl<T: Read, U: Read> Read for Chain<T, U> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.done_first {
+            match self.first.read(buf)? {
+                0 if !buf.is_empty() => self.done_first = true,
+                n => return Ok(n),
+            }
+        }
+        self.second.read(buf)
+    }
+}
+
 pub struct Bytes<T> {
     inner: T,
 }
@@ -231,12 +474,10 @@ impl<R: Read> core::iter::Iterator for Bytes<R> {
 
     fn next(&mut self) -> Option<Result<u8>> {
         let mut byte = 0;
-        loop {
-            return match self.inner.read(core::slice::from_mut(&mut byte)) {
-                Ok(0) => None,
-                Ok(..) => Some(Ok(byte)),
-                Err(e) => Some(Err(e)),
-            };
+        match self.inner.read(core::slice::from_mut(&mut byte)) {
+            Ok(0) => None,
+            Ok(..) => Some(Ok(byte)),
+            Err(e) => Some(Err(e)),
         }
     }
 }
@@ -254,9 +495,42 @@ impl<T> Cursor<T> {
     pub fn new(inner: T) -> Self {
         Self { pos: 0, inner }
     }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Seek for Cursor<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.inner.as_ref().len() as u64, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(Error::Other(
+                "invalid seek to a negative or overflowing position".into(),
+            )),
+        }
+    }
 }
 
-impl<T> Cursor<T>
+impl<T> BufRead for Cursor<T>
 where
     T: AsRef<[u8]>,
 {
@@ -264,6 +538,10 @@ where
         let amt = cmp::min(self.pos, self.inner.as_ref().len() as u64);
         Ok(&self.inner.as_ref()[(amt as usize)..])
     }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
 }
 
 impl<T> Read for Cursor<T>
@@ -277,6 +555,16 @@ where
     }
 }
 
+impl BufRead for &[u8] {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        *self = &self[cmp::min(amt, self.len())..];
+    }
+}
+
 impl Read for &[u8] {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let amt = cmp::min(buf.len(), self.len());
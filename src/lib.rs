@@ -5,7 +5,7 @@
 //! See the `url` module for code representing urls.
 #![cfg_attr(not(feature = "std"), no_std)]
 
-#[cfg(not(feature = "std"))]
+#[cfg(any(test, not(feature = "std")))]
 extern crate alloc;
 
 pub mod client;
@@ -13,8 +13,17 @@ pub mod server;
 
 pub mod error;
 pub mod protocol;
+pub mod sse;
 pub mod url;
 
+mod base64;
+
+#[cfg(feature = "std")]
+pub mod date;
+
+#[cfg(feature = "std")]
+pub mod multipart;
+
 #[cfg(feature = "ssl")]
 pub mod ssl;
 
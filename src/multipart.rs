@@ -0,0 +1,200 @@
+//! Building `multipart/form-data` bodies, see RFC 7578.
+//!
+//! # Example
+//! ```rust
+//! use http_io::client::HttpRequestBuilder;
+//! use http_io::multipart::MultipartBuilder;
+//!
+//! # fn main() -> http_io::error::Result<()> {
+//! let form = MultipartBuilder::new()
+//!     .text("description", "a picture")
+//!     .file("picture", "cat.png", "image/png", std::io::empty());
+//! let builder = HttpRequestBuilder::post("http://localhost/upload")?.multipart(form);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher as _, Hasher as _};
+use std::io;
+
+/// One part of the body, the headers are already serialized along with the boundary before them.
+struct Part {
+    head: Vec<u8>,
+    body: Box<dyn io::Read>,
+}
+
+/// Builds up the parts of a `multipart/form-data` body. The parts are only read once the body
+/// returned by `into_body` is read, so files can be streamed without reading them in to memory.
+pub struct MultipartBuilder {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+/// Make a boundary unlikely to appear in any of the parts.
+fn random_boundary() -> String {
+    let mut boundary = String::from("http_io-boundary-");
+    for _ in 0..2 {
+        // Every `RandomState` is seeded differently, so this is a cheap source of randomness.
+        let hash = RandomState::new().build_hasher().finish();
+        boundary.push_str(&format!("{:016x}", hash));
+    }
+    boundary
+}
+
+/// Quote a name for a `Content-Disposition` parameter, percent-encoding the characters which
+/// can't appear in it like browsers do.
+fn quote(name: &str) -> String {
+    let escaped = name
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    format!("\"{}\"", escaped)
+}
+
+impl MultipartBuilder {
+    /// Create a `MultipartBuilder` with a randomly generated boundary.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_boundary(random_boundary())
+    }
+
+    /// Create a `MultipartBuilder` with the given boundary. It must not appear in any of the
+    /// parts.
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            parts: vec![],
+        }
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The value of the `Content-Type` header for the body.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    fn add_part(
+        mut self,
+        disposition: String,
+        content_type: Option<&str>,
+        body: Box<dyn io::Read>,
+    ) -> Self {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: {}\r\n",
+            self.boundary, disposition
+        );
+        if let Some(content_type) = content_type {
+            head.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        head.push_str("\r\n");
+        self.parts.push(Part {
+            head: head.into_bytes(),
+            body,
+        });
+        self
+    }
+
+    /// Add a text field.
+    pub fn text(self, name: &str, value: impl Into<String>) -> Self {
+        let disposition = format!("form-data; name={}", quote(name));
+        let value = value.into().into_bytes();
+        self.add_part(disposition, None, Box::new(io::Cursor::new(value)))
+    }
+
+    /// Add a file, whose contents are read from `source` as the body is sent.
+    pub fn file(
+        self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        source: impl io::Read + 'static,
+    ) -> Self {
+        let disposition = format!(
+            "form-data; name={}; filename={}",
+            quote(name),
+            quote(filename)
+        );
+        self.add_part(disposition, Some(content_type), Box::new(source))
+    }
+
+    /// Turn the parts in to the serialized body.
+    pub fn into_body(self) -> MultipartBody {
+        let mut readers: Vec<Box<dyn io::Read>> = vec![];
+        for part in self.parts {
+            readers.push(Box::new(io::Cursor::new(part.head)));
+            readers.push(part.body);
+            readers.push(Box::new(&b"\r\n"[..]));
+        }
+        let end = format!("--{}--\r\n", self.boundary).into_bytes();
+        readers.push(Box::new(io::Cursor::new(end)));
+        readers.reverse();
+        MultipartBody { readers }
+    }
+}
+
+/// A serialized `multipart/form-data` body, reading the parts as it is read.
+pub struct MultipartBody {
+    /// What is left to read, in reverse order.
+    readers: Vec<Box<dyn io::Read>>,
+}
+
+impl io::Read for MultipartBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(reader) = self.readers.last_mut() {
+            let read = reader.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.readers.pop();
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod multipart_tests {
+    use super::MultipartBuilder;
+    use std::io::Read as _;
+
+    #[test]
+    fn random_boundaries_differ() {
+        let a = MultipartBuilder::new();
+        let b = MultipartBuilder::new();
+        assert_ne!(a.boundary(), b.boundary());
+        assert_eq!(
+            a.content_type(),
+            format!("multipart/form-data; boundary={}", a.boundary())
+        );
+    }
+
+    #[test]
+    fn empty_body() {
+        let mut body = String::new();
+        MultipartBuilder::with_boundary("xyz")
+            .into_body()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "--xyz--\r\n");
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let mut body = String::new();
+        MultipartBuilder::with_boundary("xyz")
+            .text("a \"name\"\r\n", "v")
+            .into_body()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(
+            body,
+            "--xyz\r\n\
+             Content-Disposition: form-data; name=\"a %22name%22%0D%0A\"\r\n\
+             \r\n\
+             v\r\n\
+             --xyz--\r\n"
+        );
+    }
+}
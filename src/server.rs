@@ -1,5 +1,6 @@
 //! A very simple HTTP server. It is not suitable for production workloads.
-//! Users should write their own request handler which implements the `HttpRequestHandler` trait.
+//! Users should write their own request handler which implements the `HttpRequestHandler` trait,
+//! or the `HttpHandler` trait to send responses without boxing their bodies.
 //!
 //! # File Server Example
 //! ```rust
@@ -9,7 +10,7 @@
 //! use std::thread;
 //!
 //! use http_io::error::{Error, Result};
-//! use http_io::protocol::{HttpBody, HttpResponse, HttpStatus};
+//! use http_io::protocol::{HttpBody, HttpHeaders, HttpResponse, HttpStatus};
 //! use http_io::server::{HttpRequestHandler, HttpServer};
 //!
 //! struct FileHandler {
@@ -29,6 +30,7 @@
 //!     fn get<'a>(
 //!         &'a mut self,
 //!         uri: String,
+//!         _headers: &HttpHeaders,
 //!     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>> {
 //!         let path = self.file_root.join(uri.trim_start_matches("/"));
 //!         Ok(HttpResponse::new(
@@ -40,11 +42,12 @@
 //!     fn put<'a>(
 //!         &'a mut self,
 //!         uri: String,
-//!         mut stream: HttpBody<&mut I>,
+//!         _headers: &HttpHeaders,
+//!         stream: &mut HttpBody<&mut I>,
 //!     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>> {
 //!         let path = self.file_root.join(uri.trim_start_matches("/"));
 //!         let mut file = std::fs::File::create(path)?;
-//!         io::copy(&mut stream, &mut file)?;
+//!         io::copy(stream, &mut file)?;
 //!         Ok(HttpResponse::new(HttpStatus::OK, Box::new(io::empty())))
 //!     }
 //! }
@@ -68,39 +71,563 @@
 //! }
 //! ```
 use crate::io;
-use crate::protocol::{HttpBody, HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+use crate::protocol::{
+    copy_with_buf, expects_continue, parse_request_target, status_has_body, ChunkedWriter,
+    HeaderLimits, HttpBody, HttpHeaders, HttpMethod, HttpRequest, HttpResponse, HttpStatus,
+};
+use crate::url::decode_component;
 #[cfg(not(feature = "std"))]
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
+    format,
     string::{String, ToString},
+    vec::Vec,
 };
+use core::net::SocketAddr;
 use core::result::Result;
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
 
 type HttpResult<T> = core::result::Result<T, HttpResponse<Box<dyn io::Read>>>;
 
+/// Errors parsing what the client sent, like a malformed request line or a truncated body, are
+/// the client's fault and become `400 Bad Request`. Errors without a status of their own become
+/// `500 Internal Server Error`.
 impl From<crate::error::Error> for HttpResponse<Box<dyn io::Read>> {
     fn from(error: crate::error::Error) -> Self {
         match error {
+            crate::error::Error::BadRequest(reason) => {
+                HttpResponse::from_string(HttpStatus::BadRequest, reason)
+            }
+            e @ (crate::error::Error::ParseError(_)
+            | crate::error::Error::ParseIntError(_)
+            | crate::error::Error::Utf8Error(_)
+            | crate::error::Error::UnexpectedEof(_)
+            | crate::error::Error::UrlError(_)) => {
+                HttpResponse::from_string(HttpStatus::BadRequest, e.to_string())
+            }
             crate::error::Error::LengthRequired => {
                 HttpResponse::from_string(HttpStatus::LengthRequired, "length required")
             }
+            crate::error::Error::HeadersTooLarge => HttpResponse::from_string(
+                HttpStatus::RequestHeaderFieldsTooLarge,
+                "request headers too large",
+            ),
+            crate::error::Error::BodyTooLarge => HttpResponse::from_string(
+                HttpStatus::RequestEntityTooLarge,
+                "request body too large",
+            ),
             e => HttpResponse::from_string(HttpStatus::InternalServerError, e.to_string()),
         }
     }
 }
 
+/// Tell a client waiting with `Expect: 100-continue` to go ahead and send the body.
+fn send_continue<S: io::Read + io::Write>(
+    headers: &HttpHeaders,
+    body: &mut HttpBody<&mut S>,
+) -> crate::error::Result<()> {
+    if let (true, Some(stream)) = (expects_continue(headers), body.get_mut()) {
+        write!(stream, "HTTP/1.1 {}\r\n\r\n", HttpStatus::Continue)?;
+        io::Write::flush(stream)?;
+    }
+    Ok(())
+}
+
+/// Write the response to the stream, encoding the body if the response says it is chunked. Only
+/// the status and headers are written if `send_body` is false, as for a response to `HEAD`.
+fn write_response<S: io::Write, B: io::Read>(
+    stream: &mut S,
+    mut response: HttpResponse<B>,
+    send_body: bool,
+) -> io::Result<()> {
+    // Write the status line and headers together rather than a line at a time.
+    let mut stream = io::BufWriter::new(stream);
+    response.serialize(&mut stream)?;
+    let mut buf = [0; 8 * 1024];
+    if !send_body {
+        // The headers still describe the body, as they would for a GET.
+    } else if response.get_header("Transfer-Encoding") == Some("chunked") {
+        let mut chunked = ChunkedWriter::new(&mut stream);
+        copy_with_buf(&mut response.body, &mut chunked, &mut buf)?;
+        chunked.finish()?;
+    } else {
+        copy_with_buf(&mut response.body, &mut stream, &mut buf)?;
+    }
+    io::Write::flush(&mut stream)
+}
+
 /// Represents the ability to accept a new abstract connection.
 pub trait Listen {
     type Stream: io::Read + io::Write;
-    fn accept(&self) -> crate::error::Result<Self::Stream>;
+
+    /// Accept a new connection, returning it along with the address of the peer if there is one.
+    fn accept(&self) -> crate::error::Result<(Self::Stream, Option<SocketAddr>)>;
+}
+
+impl<L: Listen + ?Sized> Listen for &L {
+    type Stream = L::Stream;
+    fn accept(&self) -> crate::error::Result<(L::Stream, Option<SocketAddr>)> {
+        (**self).accept()
+    }
 }
 
 #[cfg(feature = "std")]
 impl Listen for std::net::TcpListener {
     type Stream = std::net::TcpStream;
-    fn accept(&self) -> crate::error::Result<std::net::TcpStream> {
-        let (stream, _) = std::net::TcpListener::accept(self)?;
-        Ok(stream)
+    fn accept(&self) -> crate::error::Result<(std::net::TcpStream, Option<SocketAddr>)> {
+        let (stream, addr) = std::net::TcpListener::accept(self)?;
+        // On some platforms streams accepted from a non-blocking listener are non-blocking too.
+        stream.set_nonblocking(false)?;
+        // Responses are written in a few pieces, none of which should wait on the client's ACK.
+        stream.set_nodelay(true)?;
+        Ok((stream, Some(addr)))
+    }
+}
+
+/// One direction of a `MemoryStream`.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Pipe {
+    buffer: std::collections::VecDeque<u8>,
+    write_closed: bool,
+    read_closed: bool,
+}
+
+#[cfg(feature = "std")]
+type SharedPipe = std::sync::Arc<(std::sync::Mutex<Pipe>, std::sync::Condvar)>;
+
+/// One end of an in-memory connection, made with `MemoryListener::connect`. Whatever is written
+/// to one end is read from the other. Reading waits for the other end to write something, and
+/// returns end of file once it has been dropped or `shutdown_write` has been called on it.
+///
+/// *This type is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub struct MemoryStream {
+    read: SharedPipe,
+    write: SharedPipe,
+}
+
+#[cfg(feature = "std")]
+impl MemoryStream {
+    /// Make both ends of a new connection.
+    pub fn pair() -> (Self, Self) {
+        let (a, b) = (SharedPipe::default(), SharedPipe::default());
+        (
+            Self {
+                read: a.clone(),
+                write: b.clone(),
+            },
+            Self { read: b, write: a },
+        )
+    }
+
+    /// Signal end of file to the other end, like `TcpStream::shutdown` with `Shutdown::Write`.
+    /// Reading from this end still works.
+    pub fn shutdown_write(&self) {
+        let (pipe, condvar) = &*self.write;
+        pipe.lock().unwrap().write_closed = true;
+        condvar.notify_all();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.shutdown_write();
+        let (pipe, condvar) = &*self.read;
+        pipe.lock().unwrap().read_closed = true;
+        condvar.notify_all();
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (pipe, condvar) = &*self.read;
+        let mut pipe = pipe.lock().unwrap();
+        while pipe.buffer.is_empty() && !pipe.write_closed && !buf.is_empty() {
+            pipe = condvar.wait(pipe).unwrap();
+        }
+        io::Read::read(&mut pipe.buffer, buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (pipe, condvar) = &*self.write;
+        let mut pipe = pipe.lock().unwrap();
+        if pipe.read_closed || pipe.write_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        pipe.buffer.extend(buf);
+        condvar.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `Listen` implementation whose connections are `MemoryStream`s made in the same process,
+/// for testing request handlers without a network. Accepting never waits: like a non-blocking
+/// `TcpListener`, it fails with an `io::ErrorKind::WouldBlock` error if no connection is waiting.
+///
+/// ```rust
+/// use http_io::protocol::{HttpResponse, HttpStatus};
+/// use http_io::server::{HttpServer, MemoryListener, Router};
+/// use std::io::{Read as _, Write as _};
+///
+/// let router = Router::new().route(http_io::protocol::HttpMethod::Get, "/", |_, _, _| {
+///     Ok(HttpResponse::from_string(HttpStatus::OK, "hi"))
+/// });
+/// let listener = MemoryListener::new();
+/// let mut client = listener.connect();
+/// let mut server = HttpServer::new(&listener, router);
+///
+/// client.write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+/// server.serve_one().unwrap();
+///
+/// let mut response = String::new();
+/// client.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+/// assert!(response.ends_with("\r\n\r\nhi"));
+/// ```
+///
+/// *This type is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct MemoryListener {
+    pending: std::sync::Mutex<std::collections::VecDeque<MemoryStream>>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryListener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make a new connection to the listener, returning the client's end of it. The server's end
+    /// is returned by the next `accept`.
+    pub fn connect(&self) -> MemoryStream {
+        let (client, server) = MemoryStream::pair();
+        self.pending.lock().unwrap().push_back(server);
+        client
+    }
+}
+
+#[cfg(feature = "std")]
+impl Listen for MemoryListener {
+    type Stream = MemoryStream;
+
+    fn accept(&self) -> crate::error::Result<(MemoryStream, Option<SocketAddr>)> {
+        let stream = self.pending.lock().unwrap().pop_front();
+        stream
+            .map(|stream| (stream, None))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock).into())
+    }
+}
+
+/// A connection served without blocking by `HttpServer::serve_nonblocking`. It holds on to what
+/// has been read of the next request, and what couldn't be written yet of the last response.
+///
+/// *This type is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub struct NonBlockingConnection<S> {
+    stream: S,
+    input: Vec<u8>,
+    progress: RequestProgress,
+    output: Vec<u8>,
+    written: usize,
+    sent_continue: bool,
+    closing: bool,
+}
+
+#[cfg(feature = "std")]
+impl<S: io::Read + io::Write> NonBlockingConnection<S> {
+    /// Serve requests off the given stream, which should already be non-blocking, like a
+    /// `TcpStream` after `set_nonblocking(true)`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            input: Vec::new(),
+            progress: RequestProgress::default(),
+            output: Vec::new(),
+            written: 0,
+            sent_continue: false,
+            closing: false,
+        }
+    }
+
+    /// The stream, for instance to register it with `poll`.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Whether there is a response waiting to be written, in which case the connection should be
+    /// served again once the stream is writable rather than readable.
+    pub fn wants_write(&self) -> bool {
+        self.written < self.output.len()
+    }
+
+    /// Write as much of the pending output as the stream takes.
+    fn write_output(&mut self) -> io::Result<()> {
+        while self.written < self.output.len() {
+            match self.stream.write(&self.output[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => self.written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.output.clear();
+        self.written = 0;
+        self.stream.flush()
+    }
+}
+
+/// The stream a handler reads a request from when it is served by `HttpServer::serve_nonblocking`.
+/// All of the request has been read off the connection already. Whatever is written to it, like
+/// interim responses, is sent on once the connection is ready for it.
+///
+/// *This type is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub struct BufferedStream {
+    input: io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl io::Read for BufferedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for BufferedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The largest request head, or chunk framing of a request body, `HttpServer::serve_nonblocking`
+/// holds in memory. The header limits of the server can make it smaller.
+#[cfg(feature = "std")]
+const MAX_BUFFERED_HEAD: usize = 64 * 1024;
+
+/// The largest request body `HttpServer::serve_nonblocking` holds in memory when the server has
+/// no `max_body_size`.
+#[cfg(feature = "std")]
+const MAX_BUFFERED_BODY: u64 = 1024 * 1024;
+
+/// How far `HttpServer::serve_nonblocking` has got through the request at the start of the input
+/// of a connection, so the input doesn't have to be parsed again from the start as more arrives.
+#[cfg(feature = "std")]
+enum RequestProgress {
+    /// The end of the head isn't in the first `scanned` bytes.
+    Head { scanned: usize },
+    /// The request ends at `end`.
+    Body { end: usize, expects_continue: bool },
+    /// The chunks of a chunked body starting at `body_start` have been read up to `offset`,
+    /// adding up to `size` bytes. Once the last chunk has been read, `trailers` is set.
+    Chunks {
+        body_start: usize,
+        offset: usize,
+        size: u64,
+        trailers: bool,
+        expects_continue: bool,
+    },
+}
+
+#[cfg(feature = "std")]
+impl Default for RequestProgress {
+    fn default() -> Self {
+        RequestProgress::Head { scanned: 0 }
+    }
+}
+
+/// How much of a request `HttpServer::serve_nonblocking` has read.
+#[cfg(feature = "std")]
+enum BufferedRequest {
+    /// More of the request is needed. The client may be waiting for `100 Continue` to send the
+    /// body.
+    Partial { expects_continue: bool },
+    /// All of the request is in the first `length` bytes of the input, or enough to know it is
+    /// malformed.
+    Complete { length: usize },
+    /// The request is too large to hold in memory.
+    Rejected(crate::error::Error),
+}
+
+/// Find out if `input` holds a whole request, picking up from where `progress` left off. Only the
+/// framing is checked, anything malformed is for `serve_request` to respond to.
+#[cfg(feature = "std")]
+fn buffered_request(
+    progress: &mut RequestProgress,
+    input: &[u8],
+    header_limits: HeaderLimits,
+    max_body_size: Option<u64>,
+) -> BufferedRequest {
+    use crate::error::Error;
+
+    let max_head = core::cmp::min(header_limits.max_total_size, MAX_BUFFERED_HEAD);
+    let max_body = max_body_size.unwrap_or(MAX_BUFFERED_BODY);
+    loop {
+        match progress {
+            RequestProgress::Head { scanned } => {
+                // Empty lines before the request line are ignored, see RFC 7230 section 3.5.
+                let start = match input.iter().position(|&b| b != b'\r' && b != b'\n') {
+                    Some(start) => start,
+                    None => {
+                        return BufferedRequest::Partial {
+                            expects_continue: false,
+                        }
+                    }
+                };
+                // Start a little before where the last scan ended, the empty line may straddle it.
+                let from = core::cmp::max(start, scanned.saturating_sub(2));
+                let head_end = (from..input.len()).find_map(|i| match &input[i..] {
+                    [b'\n', b'\n', ..] => Some(i + 2),
+                    [b'\n', b'\r', b'\n', ..] => Some(i + 3),
+                    _ => None,
+                });
+                let head_end = match head_end {
+                    Some(head_end) => head_end,
+                    None if input.len() - start > max_head => {
+                        return BufferedRequest::Rejected(Error::HeadersTooLarge)
+                    }
+                    None => {
+                        *scanned = input.len();
+                        return BufferedRequest::Partial {
+                            expects_continue: false,
+                        };
+                    }
+                };
+                let head = io::BufReader::new(&input[..head_end]);
+                let request = match HttpRequest::deserialize_with_limits(head, header_limits) {
+                    Ok(request) => request,
+                    Err(_) => {
+                        return BufferedRequest::Complete {
+                            length: input.len(),
+                        }
+                    }
+                };
+                let expects_continue = expects_continue(&request.headers);
+                *progress = match &request.body {
+                    HttpBody::Chunked(_) => RequestProgress::Chunks {
+                        body_start: head_end,
+                        offset: head_end,
+                        size: 0,
+                        trailers: false,
+                        expects_continue,
+                    },
+                    HttpBody::Limited(body) if body.limit() > max_body => {
+                        return BufferedRequest::Rejected(Error::BodyTooLarge)
+                    }
+                    HttpBody::Limited(body) => RequestProgress::Body {
+                        end: head_end + body.limit() as usize,
+                        expects_continue,
+                    },
+                    // A request without a length has no body.
+                    HttpBody::ReadTilClose(_) => RequestProgress::Body {
+                        end: head_end,
+                        expects_continue,
+                    },
+                };
+            }
+            RequestProgress::Body {
+                end,
+                expects_continue,
+            } => {
+                return if input.len() >= *end {
+                    BufferedRequest::Complete { length: *end }
+                } else {
+                    BufferedRequest::Partial {
+                        expects_continue: *expects_continue,
+                    }
+                };
+            }
+            RequestProgress::Chunks {
+                body_start,
+                offset,
+                size,
+                trailers,
+                expects_continue,
+            } => {
+                // Everything but the chunks' data, like the size lines and trailers, counts
+                // towards the same limit as the head.
+                let framing = (*offset - *body_start) as u64 - *size;
+                let line_end = input[*offset..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map(|i| *offset + i + 1);
+                let end = match line_end {
+                    _ if framing > max_head as u64 => {
+                        return BufferedRequest::Rejected(Error::HeadersTooLarge)
+                    }
+                    Some(end) => end,
+                    None if input.len() - *offset > max_head => {
+                        return BufferedRequest::Rejected(Error::HeadersTooLarge)
+                    }
+                    None => {
+                        return BufferedRequest::Partial {
+                            expects_continue: *expects_continue,
+                        }
+                    }
+                };
+                let line = &input[*offset..end];
+                if *trailers {
+                    // The trailers end with an empty line.
+                    *offset = end;
+                    if line == b"\n" || line == b"\r\n" {
+                        return BufferedRequest::Complete { length: end };
+                    }
+                    continue;
+                }
+                let chunk_size = core::str::from_utf8(line)
+                    .ok()
+                    .and_then(|line| line.split(';').next())
+                    .and_then(|size| u64::from_str_radix(size.trim_end(), 16).ok());
+                let chunk_size = match chunk_size {
+                    Some(chunk_size) => chunk_size,
+                    None => {
+                        return BufferedRequest::Complete {
+                            length: input.len(),
+                        }
+                    }
+                };
+                if chunk_size == 0 {
+                    *offset = end;
+                    *trailers = true;
+                    continue;
+                }
+                if size.saturating_add(chunk_size) > max_body {
+                    return BufferedRequest::Rejected(Error::BodyTooLarge);
+                }
+                // The chunk's data is followed by a line ending.
+                let chunk_end = end + chunk_size as usize + 2;
+                if input.len() < chunk_end {
+                    return BufferedRequest::Partial {
+                        expects_continue: *expects_continue,
+                    };
+                }
+                *size += chunk_size;
+                *offset = chunk_end;
+            }
+        }
     }
 }
 
@@ -108,9 +635,26 @@ impl Listen for std::net::TcpListener {
 pub trait HttpRequestHandler<I: io::Read> {
     type Error: Into<HttpResponse<Box<dyn io::Read>>>;
 
+    /// Called when a new connection is accepted, before any of its requests are handled. All the
+    /// requests until the next call come from the given peer. The address is `None` if the
+    /// `Listen` implementation doesn't know it.
+    fn accepted(&mut self, _peer_addr: Option<SocketAddr>) {}
+
+    fn connect<'a>(
+        &'a mut self,
+        _uri: String,
+        _headers: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        Ok(HttpResponse::from_string(
+            HttpStatus::MethodNotAllowed,
+            "CONNECT not allowed",
+        ))
+    }
+
     fn delete<'a>(
         &'a mut self,
         _uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -121,6 +665,7 @@ pub trait HttpRequestHandler<I: io::Read> {
     fn get<'a>(
         &'a mut self,
         _uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -131,6 +676,7 @@ pub trait HttpRequestHandler<I: io::Read> {
     fn head<'a>(
         &'a mut self,
         _uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -141,6 +687,7 @@ pub trait HttpRequestHandler<I: io::Read> {
     fn options<'a>(
         &'a mut self,
         _uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -148,10 +695,23 @@ pub trait HttpRequestHandler<I: io::Read> {
         ))
     }
 
+    fn patch<'a>(
+        &'a mut self,
+        _uri: String,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        Ok(HttpResponse::from_string(
+            HttpStatus::MethodNotAllowed,
+            "PATCH not allowed",
+        ))
+    }
+
     fn put<'a>(
         &'a mut self,
         _uri: String,
-        _stream: HttpBody<&mut I>,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -162,7 +722,8 @@ pub trait HttpRequestHandler<I: io::Read> {
     fn post<'a>(
         &'a mut self,
         _uri: String,
-        _stream: HttpBody<&mut I>,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -173,6 +734,7 @@ pub trait HttpRequestHandler<I: io::Read> {
     fn trace<'a>(
         &'a mut self,
         _uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -181,57 +743,474 @@ pub trait HttpRequestHandler<I: io::Read> {
     }
 }
 
+/// Like `HttpRequestHandler`, but with one method for every request and a concrete type for the
+/// body of its responses. Responses whose body type is known can be sent without allocating or
+/// dispatching dynamically, which adds up when serving lots of small responses.
+///
+/// Every `HttpRequestHandler` is also an `HttpHandler` whose response body is boxed. Because of
+/// that, an `HttpHandler` has to be implemented for the stream type of the `Listen` it is used
+/// with, like `std::net::TcpStream`, rather than for every stream type.
+pub trait HttpHandler<I: io::Read> {
+    type Body<'a>: io::Read
+    where
+        Self: 'a;
+    type Error: Into<HttpResponse<Box<dyn io::Read>>>;
+
+    /// Called when a new connection is accepted, see `HttpRequestHandler::accepted`.
+    fn accepted(&mut self, _peer_addr: Option<SocketAddr>) {}
+
+    /// Respond to a request. The body is only given for `PATCH`, `POST` and `PUT` requests.
+    fn handle<'a>(
+        &'a mut self,
+        method: HttpMethod,
+        uri: String,
+        headers: &HttpHeaders,
+        body: Option<&mut HttpBody<&mut I>>,
+    ) -> Result<HttpResponse<Self::Body<'a>>, Self::Error>;
+
+    /// Respond to a request like `handle`, but with the chance to send informational responses
+    /// like `103 Early Hints` through `interim` before the final response is ready. By default it
+    /// just calls `handle`.
+    fn handle_with_interim<'a>(
+        &'a mut self,
+        method: HttpMethod,
+        uri: String,
+        headers: &HttpHeaders,
+        interim: &mut Interim<'_, '_, I>,
+    ) -> Result<HttpResponse<Self::Body<'a>>, Self::Error> {
+        self.handle(method, uri, headers, interim.body())
+    }
+}
+
+/// Sends informational (`1xx`) responses to a request ahead of the final response, see
+/// `HttpHandler::handle_with_interim`. It also holds the request body, since both are sent over
+/// the same stream.
+pub struct Interim<'b, 's, I: io::Read> {
+    body: &'b mut HttpBody<&'s mut I>,
+    has_body: bool,
+    accepts_interim: bool,
+}
+
+impl<'s, I: io::Read> Interim<'_, 's, I> {
+    /// The request body, only given for `PATCH`, `POST` and `PUT` requests.
+    pub fn body(&mut self) -> Option<&mut HttpBody<&'s mut I>> {
+        if self.has_body {
+            Some(&mut *self.body)
+        } else {
+            None
+        }
+    }
+
+    /// Send an informational response with the given headers right away. Fails if the status isn't
+    /// informational, or is `101 Switching Protocols` which ends the exchange rather than coming
+    /// before the final response. Also fails in the middle of reading a chunk of the request body.
+    /// Does nothing for requests older than HTTP/1.1, since those clients don't expect informational
+    /// responses, see RFC 7231 section 6.2.
+    pub fn send(&mut self, status: HttpStatus, headers: &HttpHeaders) -> crate::error::Result<()>
+    where
+        I: io::Write,
+    {
+        if !status.is_informational() || status == HttpStatus::SwitchingProtocols {
+            return Err(crate::error::Error::Other(format!(
+                "{} is not an interim response",
+                status
+            )));
+        }
+        if !self.accepts_interim {
+            return Ok(());
+        }
+        let stream = self.body.get_mut().ok_or_else(|| {
+            crate::error::Error::Other("in the middle of reading the request body".into())
+        })?;
+
+        // Write the status line and headers together rather than a line at a time.
+        let mut head = format!("HTTP/1.1 {}\r\n", status).into_bytes();
+        headers.serialize(&mut head)?;
+        head.extend_from_slice(b"\r\n");
+        stream.write_all(&head)?;
+        io::Write::flush(stream)?;
+        Ok(())
+    }
+}
+
+impl<I: io::Read, H: HttpRequestHandler<I>> HttpHandler<I> for H {
+    type Body<'a>
+        = Box<dyn io::Read + 'a>
+    where
+        Self: 'a;
+    type Error = H::Error;
+
+    fn accepted(&mut self, peer_addr: Option<SocketAddr>) {
+        HttpRequestHandler::accepted(self, peer_addr)
+    }
+
+    fn handle<'a>(
+        &'a mut self,
+        method: HttpMethod,
+        uri: String,
+        headers: &HttpHeaders,
+        body: Option<&mut HttpBody<&mut I>>,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        match (method, body) {
+            (HttpMethod::Connect, _) => self.connect(uri, headers),
+            (HttpMethod::Delete, _) => self.delete(uri, headers),
+            (HttpMethod::Get, _) => self.get(uri, headers),
+            (HttpMethod::Head, _) => self.head(uri, headers),
+            (HttpMethod::Options, _) => self.options(uri, headers),
+            (HttpMethod::Patch, Some(body)) => self.patch(uri, headers, body),
+            (HttpMethod::Post, Some(body)) => self.post(uri, headers, body),
+            (HttpMethod::Put, Some(body)) => self.put(uri, headers, body),
+            (HttpMethod::Trace, _) => self.trace(uri, headers),
+            (HttpMethod::Patch | HttpMethod::Post | HttpMethod::Put, None) => Ok(
+                HttpResponse::from_string(HttpStatus::LengthRequired, "length required"),
+            ),
+        }
+    }
+}
+
+/// Make sure the client can find the end of the response, chunking the body if it has no length
+/// and the client supports it. Returns false if the end can only be signaled by closing the
+/// connection.
+fn frame_response<B: io::Read>(response: &mut HttpResponse<B>, accepts_chunked: bool) -> bool {
+    let has_length = response.get_header("Content-Length").is_some()
+        || response.get_header("Transfer-Encoding").is_some()
+        || response.body.content_length().is_some();
+    if !has_length && accepts_chunked && status_has_body(response.status) {
+        response.add_header("Transfer-Encoding", "chunked");
+    }
+    has_length || response.get_header("Transfer-Encoding").is_some()
+}
+
+/// Add a `Date` header with the current time, unless there already is one. Without `std` there is
+/// no clock to get the time from.
+fn add_date(headers: &mut HttpHeaders) {
+    #[cfg(feature = "std")]
+    if headers.get("Date").is_none() {
+        headers.insert(
+            "Date",
+            crate::date::format_http_date(std::time::SystemTime::now()),
+        );
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = headers;
+}
+
+/// The outcome of serving one request off a stream.
+struct ServedRequest<B: io::Read> {
+    /// The handler's response, or the server's own when the handler couldn't be called.
+    response: HttpResult<HttpResponse<B>>,
+    /// Whether the stream can be used for another request.
+    keep_alive: bool,
+    /// Whether the response body should be sent, it isn't for `HEAD` requests.
+    send_body: bool,
+}
+
+impl<B: io::Read> ServedRequest<B> {
+    fn write<S: io::Write>(self, stream: &mut S) -> io::Result<()> {
+        match self.response {
+            Ok(response) => write_response(stream, response, self.send_body),
+            Err(response) => write_response(stream, response, self.send_body),
+        }
+    }
+}
+
+/// Called with errors from serving connections which there is no caller to return them to, see
+/// `HttpServer::set_error_callback`.
+#[cfg(feature = "std")]
+type ErrorCallback = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+#[cfg(feature = "std")]
+fn report_error(error_callback: &Option<ErrorCallback>, error: &io::Error) {
+    if let Some(error_callback) = error_callback {
+        error_callback(error);
+    }
+}
+
 /// A simple HTTP server. Not suited for production workloads, better used in tests and small
 /// projects.
-pub struct HttpServer<L: Listen, H: HttpRequestHandler<L::Stream>> {
+pub struct HttpServer<L: Listen, H: HttpHandler<L::Stream>> {
     connection_stream: L,
     request_handler: H,
+    header_limits: HeaderLimits,
+    head_from_get: bool,
+    max_body_size: Option<u64>,
+    reject_when_busy: bool,
+    send_date: bool,
+    echo_trace: bool,
+    #[cfg(feature = "std")]
+    error_callback: Option<ErrorCallback>,
 }
 
-impl<L: Listen, H: HttpRequestHandler<L::Stream>> HttpServer<L, H> {
+impl<L: Listen, H: HttpHandler<L::Stream>> HttpServer<L, H> {
     pub fn new(connection_stream: L, request_handler: H) -> Self {
+        Self::with_limits(connection_stream, request_handler, HeaderLimits::default())
+    }
+
+    /// Create a server which rejects requests whose request line and headers exceed the given
+    /// limits, rather than reading them in to memory.
+    pub fn with_limits(
+        connection_stream: L,
+        request_handler: H,
+        header_limits: HeaderLimits,
+    ) -> Self {
         HttpServer {
             connection_stream,
             request_handler,
+            header_limits,
+            head_from_get: false,
+            max_body_size: None,
+            reject_when_busy: false,
+            send_date: true,
+            echo_trace: false,
+            #[cfg(feature = "std")]
+            error_callback: None,
         }
     }
 
+    /// Reject request bodies longer than `max_body_size` bytes with `413 Request Entity Too
+    /// Large`. A body with a longer `Content-Length` is rejected without calling the handler,
+    /// otherwise reading the body fails once it goes over. No limit is enforced by default.
+    pub fn set_max_body_size(&mut self, max_body_size: Option<u64>) {
+        self.max_body_size = max_body_size;
+    }
+
+    /// When serving with `serve_forever_threaded`, answer new connections with `503 Service
+    /// Unavailable` while every thread is busy, instead of leaving them waiting to be accepted.
+    /// Off by default.
+    pub fn set_reject_when_busy(&mut self, reject_when_busy: bool) {
+        self.reject_when_busy = reject_when_busy;
+    }
+
+    /// Add a `Date` header with the current time to responses which don't already have one, as
+    /// RFC 7231 requires of origin servers with a clock. On by default, only available with the
+    /// `"std"` feature.
+    pub fn set_send_date(&mut self, send_date: bool) {
+        self.send_date = send_date;
+    }
+
+    /// Answer `TRACE` requests by echoing the request line and headers back as a `message/http`
+    /// body, instead of calling the handler's `trace`. Headers likely to hold credentials aren't
+    /// echoed. Off by default, since reflecting requests back can help cross-site attacks.
+    pub fn set_echo_trace(&mut self, echo_trace: bool) {
+        self.echo_trace = echo_trace;
+    }
+
+    /// Call `error_callback` with the errors `serve_forever`, `serve_until` and
+    /// `serve_forever_threaded` run in to while serving connections, since they carry on serving
+    /// instead of returning them. They are ignored by default.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn set_error_callback(
+        &mut self,
+        error_callback: impl Fn(&io::Error) + Send + Sync + 'static,
+    ) {
+        self.error_callback = Some(Box::new(error_callback));
+    }
+
+    #[cfg(feature = "std")]
+    fn report_error(&self, error: &io::Error) {
+        report_error(&self.error_callback, error);
+    }
+
+    /// Answer `HEAD` requests by calling the handler's `get` and sending only the status and
+    /// headers of its response, instead of calling the handler's `head`. Off by default.
+    pub fn set_head_from_get(&mut self, head_from_get: bool) {
+        self.head_from_get = head_from_get;
+    }
+
+    /// Accept one new HTTP stream, serve one request off it and close it.
     pub fn serve_one(&mut self) -> io::Result<()> {
-        let mut stream = self.connection_stream.accept()?;
-        let mut response = match self.serve_one_inner(&mut stream) {
-            Ok(response) => response,
-            Err(response) => response,
+        let (stream, peer_addr) = self.connection_stream.accept()?;
+        self.serve_accepted(stream, peer_addr)
+    }
+
+    /// Serve one request off a newly accepted stream and close it.
+    fn serve_accepted(
+        &mut self,
+        mut stream: <L as Listen>::Stream,
+        peer_addr: Option<SocketAddr>,
+    ) -> io::Result<()> {
+        self.request_handler.accepted(peer_addr);
+        if let Some(served) = self.serve_request(&mut stream, false) {
+            served.write(&mut stream)?;
+        }
+        Ok(())
+    }
+
+    /// Serve requests off the given stream until the client closes it or asks for it to be closed,
+    /// or until a response is sent whose end can only be signaled by closing the connection.
+    /// Pipelining isn't supported, the client must wait for each response before sending the next
+    /// request.
+    pub fn serve_connection(&mut self, mut stream: <L as Listen>::Stream) -> io::Result<()> {
+        loop {
+            let served = match self.serve_request(&mut stream, true) {
+                Some(served) => served,
+                None => return Ok(()),
+            };
+            let keep_alive = served.keep_alive;
+            served.write(&mut stream)?;
+
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Serve one request off the given stream. Returns `None` if the client closed the stream
+    /// instead of sending a request.
+    ///
+    /// Response bodies without a known length are sent chunked if the client supports it, so
+    /// their end can be found without closing the connection.
+    fn serve_request<'a, I: io::Read + io::Write>(
+        &'a mut self,
+        stream: &mut I,
+        allow_keep_alive: bool,
+    ) -> Option<ServedRequest<<H as HttpHandler<I>>::Body<'a>>>
+    where
+        H: HttpHandler<I>,
+    {
+        let stream = io::BufReader::new(stream);
+        let mut request = match HttpRequest::deserialize_with_limits(stream, self.header_limits) {
+            Ok(request) => request,
+            Err(crate::error::Error::UnexpectedEof(_)) => return None,
+            Err(e) => {
+                let mut response: HttpResponse<Box<dyn io::Read>> = e.into();
+                response.add_header("Connection", "close");
+                if self.send_date {
+                    add_date(&mut response.headers);
+                }
+                return Some(ServedRequest {
+                    response: Err(response),
+                    keep_alive: false,
+                    send_body: true,
+                });
+            }
         };
+        let keep_alive = allow_keep_alive && request.keep_alive();
+        let accepts_chunked = request.accepts_chunked();
+        let send_body = request.method != HttpMethod::Head;
+        let send_date = self.send_date;
+        let max_body_size = self.max_body_size;
 
-        response.serialize(&mut stream)?;
-        io::copy(&mut response.body, &mut stream)?;
+        let mut response = self.dispatch(&mut request);
+        let (framed, headers) = match &mut response {
+            Ok(response) => (
+                frame_response(response, accepts_chunked),
+                &mut response.headers,
+            ),
+            Err(response) => (
+                frame_response(response, accepts_chunked),
+                &mut response.headers,
+            ),
+        };
 
-        Ok(())
+        // The client can only find the end of the response if it has a length or is chunked, and
+        // we can only find the next request if all of this one's body has been read. A request
+        // without a length has no body. Rather than read more than the size limit of body just to
+        // throw it away, the connection is closed.
+        let keep_alive = keep_alive
+            && framed
+            && match &mut request.body {
+                HttpBody::ReadTilClose(_) => true,
+                body => {
+                    max_body_size.is_none_or(|max| body.limit_size(max).is_ok())
+                        && body.drain().is_ok()
+                }
+            };
+        if !keep_alive && headers.get("Connection").is_none() {
+            headers.insert("Connection", "close");
+        }
+        if send_date {
+            add_date(headers);
+        }
+
+        Some(ServedRequest {
+            response,
+            keep_alive,
+            send_body,
+        })
     }
 
     /// Accept one new HTTP stream and serve one request off it.
+    #[allow(clippy::result_large_err)]
     pub fn serve_one_inner<'a>(
         &'a mut self,
         stream: &mut <L as Listen>::Stream,
-    ) -> HttpResult<HttpResponse<Box<dyn io::Read + 'a>>> {
-        let request = HttpRequest::deserialize(io::BufReader::new(stream))?;
-
-        match request.method {
-            HttpMethod::Delete => self.request_handler.delete(request.uri),
-            HttpMethod::Get => self.request_handler.get(request.uri),
-            HttpMethod::Head => self.request_handler.head(request.uri),
-            HttpMethod::Options => self.request_handler.options(request.uri),
-            HttpMethod::Post => {
-                request.body.require_length()?;
-                self.request_handler.post(request.uri, request.body)
-            }
-            HttpMethod::Put => {
-                request.body.require_length()?;
-                self.request_handler.put(request.uri, request.body)
-            }
-            HttpMethod::Trace => self.request_handler.trace(request.uri),
+    ) -> HttpResult<HttpResponse<H::Body<'a>>> {
+        let stream = io::BufReader::new(stream);
+        let mut request = HttpRequest::deserialize_with_limits(stream, self.header_limits)?;
+        self.dispatch(&mut request)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn dispatch<'a, I: io::Read + io::Write>(
+        &'a mut self,
+        request: &mut HttpRequest<&mut I>,
+    ) -> HttpResult<HttpResponse<<H as HttpHandler<I>>::Body<'a>>>
+    where
+        H: HttpHandler<I>,
+    {
+        if request.method == HttpMethod::Trace && self.echo_trace {
+            let mut response = HttpResponse::from_string(HttpStatus::OK, request.trace_message());
+            response.add_header("Content-Type", "message/http");
+            return Err(response);
+        }
+        let (method, uri) = (request.method, core::mem::take(&mut request.uri));
+        let accepts_interim = request.accepts_interim();
+        let (headers, body) = (&request.headers, &mut request.body);
+
+        // The asterisk-form of request target is only for OPTIONS, see RFC 7230 section 5.3.4.
+        if uri == "*" && method != HttpMethod::Options {
+            return Err(HttpResponse::from_string(
+                HttpStatus::BadRequest,
+                "* is only a valid target for OPTIONS",
+            ));
+        }
+        let method = match method {
+            HttpMethod::Head if self.head_from_get => HttpMethod::Get,
+            method => method,
+        };
+        let has_body = matches!(
+            method,
+            HttpMethod::Patch | HttpMethod::Post | HttpMethod::Put
+        );
+        if has_body {
+            self.prepare_body(headers, body)?;
+        }
+        let mut interim = Interim {
+            body: &mut *body,
+            has_body,
+            accepts_interim,
+        };
+        let result = HttpHandler::<I>::handle_with_interim(
+            &mut self.request_handler,
+            method,
+            uri,
+            headers,
+            &mut interim,
+        )
+        .map_err(|e| e.into());
+
+        // Whatever the handler made of failing to read the body, the client is told why.
+        if body.exceeded_size_limit() {
+            return Err(crate::error::Error::BodyTooLarge.into());
+        }
+        result
+    }
+
+    /// Check the request body can be accepted before a handler reads it, asking the client to
+    /// send it if it is waiting to.
+    fn prepare_body<I: io::Read + io::Write>(
+        &self,
+        headers: &HttpHeaders,
+        body: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<()> {
+        body.require_length()?;
+        if let Some(max_body_size) = self.max_body_size {
+            body.limit_size(max_body_size)?;
         }
-        .map_err(|e| e.into())
+        send_continue(headers, body)
     }
 
     /// Run `serve_one` in a loop forever
@@ -241,23 +1220,520 @@ impl<L: Listen, H: HttpRequestHandler<L::Stream>> HttpServer<L, H> {
     pub fn serve_forever(&mut self) -> ! {
         loop {
             if let Err(e) = self.serve_one() {
-                println!("Error {:?}", e)
+                self.report_error(&e);
             }
         }
     }
-}
 
-#[cfg(test)]
-use crate::protocol::HttpHeaders;
+    /// Like `serve_forever`, but return once `shutdown` returns true. It is checked before
+    /// accepting each connection.
+    ///
+    /// Accepting blocks until a client connects, so to shut down while no clients are connecting,
+    /// the listener has to be non-blocking. For a `TcpListener` that means calling
+    /// `set_nonblocking(true)` on it. The listener is then polled until a connection comes in or
+    /// `shutdown` returns true.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn serve_until(&mut self, shutdown: impl Fn() -> bool) {
+        const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
 
-#[cfg(test)]
-#[derive(PartialEq, Debug)]
-pub struct ExpectedRequest {
-    pub expected_method: HttpMethod,
-    pub expected_uri: String,
-    pub expected_body: String,
+        while !shutdown() {
+            let result = match self.connection_stream.accept() {
+                Ok((stream, peer_addr)) => self.serve_accepted(stream, peer_addr),
+                // A non-blocking listener without a connection waiting
+                Err(crate::error::Error::IoError(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = result {
+                self.report_error(&e);
+            }
+        }
+    }
 
-    pub response_status: HttpStatus,
+    /// Serve connections forever using `num_threads` threads, each with its own clone of the
+    /// request handler. Every thread accepts connections from the listener and serves them with
+    /// `serve_connection`. A handler which panics only takes down the connection it was serving,
+    /// the thread carries on with a fresh clone of the handler.
+    ///
+    /// No more than `num_threads` connections are served at once. Any more wait to be accepted
+    /// until a thread is free, unless `set_reject_when_busy` is on. Then the calling thread
+    /// accepts every connection, handing them to the other threads, and answers those it has no
+    /// free thread for with `503 Service Unavailable`.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn serve_forever_threaded(&mut self, num_threads: usize) -> !
+    where
+        L: Sync,
+        L::Stream: Send,
+        H: Clone + Send,
+    {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        assert!(num_threads > 0, "at least one thread is needed to serve");
+        let listener = &self.connection_stream;
+        let header_limits = self.header_limits;
+        let head_from_get = self.head_from_get;
+        let send_date = self.send_date;
+        let echo_trace = self.echo_trace;
+        let max_body_size = self.max_body_size;
+        let reject_when_busy = self.reject_when_busy;
+        let error_callback = &self.error_callback;
+        let report_error = |e: &io::Error| report_error(error_callback, e);
+        // Connections handed over by the calling thread when rejecting while busy.
+        let (sender, receiver) = std::sync::mpsc::sync_channel(num_threads);
+        let receiver = std::sync::Mutex::new(receiver);
+        let in_flight = AtomicUsize::new(0);
+        let (receiver, in_flight) = (&receiver, &in_flight);
+        std::thread::scope(|scope| {
+            for _ in 0..num_threads {
+                let handler = self.request_handler.clone();
+                scope.spawn(move || {
+                    let mut server =
+                        HttpServer::with_limits(listener, handler.clone(), header_limits);
+                    server.set_head_from_get(head_from_get);
+                    server.set_send_date(send_date);
+                    server.set_echo_trace(echo_trace);
+                    server.set_max_body_size(max_body_size);
+                    loop {
+                        let accepted = if reject_when_busy {
+                            let handed_over = receiver.lock().unwrap().recv();
+                            Ok(handed_over.expect("the accepting thread never exits"))
+                        } else {
+                            server.connection_stream.accept()
+                        };
+                        let stream = match accepted {
+                            Ok((stream, peer_addr)) => {
+                                server.request_handler.accepted(peer_addr);
+                                stream
+                            }
+                            Err(e) => {
+                                report_error(&e.into());
+                                continue;
+                            }
+                        };
+                        let served = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                            server.serve_connection(stream)
+                        }));
+                        if reject_when_busy {
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                        }
+                        match served {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => report_error(&e),
+                            Err(_) => {
+                                report_error(&io::Error::other("request handler panicked"));
+                                server.request_handler = handler.clone();
+                            }
+                        }
+                    }
+                });
+            }
+
+            if !reject_when_busy {
+                return;
+            }
+            loop {
+                let (mut stream, peer_addr) = match listener.accept() {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        report_error(&e.into());
+                        continue;
+                    }
+                };
+                if in_flight.load(Ordering::SeqCst) >= num_threads {
+                    let mut response =
+                        HttpResponse::from_string(HttpStatus::ServiceUnavailable, "server busy");
+                    response.add_header("Connection", "close");
+                    if let Err(e) = write_response(&mut stream, response, true) {
+                        report_error(&e);
+                    }
+                    continue;
+                }
+                in_flight.fetch_add(1, Ordering::SeqCst);
+                // There is room in the channel for as many connections as there are threads.
+                sender.send((stream, peer_addr)).unwrap();
+            }
+        });
+        unreachable!("server threads never exit")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<L: Listen, H: HttpHandler<L::Stream> + HttpHandler<BufferedStream>> HttpServer<L, H> {
+    /// Serve requests off a non-blocking connection for as long as it can be done without waiting,
+    /// so many connections can be served from one thread with `poll` or the like.
+    ///
+    /// Fails with `io::ErrorKind::WouldBlock` once the stream isn't ready, call it again when the
+    /// stream is readable, or writable if `NonBlockingConnection::wants_write`. Returns `Ok` once
+    /// the connection is finished with, because the client closed it or it had to be closed after
+    /// the last response.
+    ///
+    /// Each request is read in full before its handler is called, so the handler never waits on
+    /// the connection. Its response is read in full before any of it is written. Requests sent
+    /// before the response to the last one are served in turn.
+    ///
+    /// As requests are held in memory, bodies larger than the `max_body_size`, or 1 MiB without
+    /// one, are rejected with `413 Request Entity Too Large`. Heads larger than the header limits,
+    /// or 64 KiB, are rejected with `431 Request Header Fields Too Large`.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    pub fn serve_nonblocking<S: io::Read + io::Write>(
+        &mut self,
+        connection: &mut NonBlockingConnection<S>,
+    ) -> io::Result<()> {
+        loop {
+            connection.write_output()?;
+            if connection.closing {
+                return Ok(());
+            }
+
+            let buffered = buffered_request(
+                &mut connection.progress,
+                &connection.input,
+                self.header_limits,
+                self.max_body_size,
+            );
+            match buffered {
+                BufferedRequest::Partial { expects_continue } => {
+                    if expects_continue && !connection.sent_continue {
+                        let status_line = format!("HTTP/1.1 {}\r\n\r\n", HttpStatus::Continue);
+                        connection.output.extend_from_slice(status_line.as_bytes());
+                        connection.sent_continue = true;
+                        continue;
+                    }
+                }
+                BufferedRequest::Complete { length } => {
+                    // Anything after the request is the start of the next one.
+                    let next = connection.input.split_off(length);
+                    let input = core::mem::replace(&mut connection.input, next);
+                    connection.progress = RequestProgress::default();
+                    // Sending `100 Continue` again when the handler is called is harmless, clients
+                    // have to skip unexpected interim responses.
+                    let mut stream = BufferedStream {
+                        input: io::Cursor::new(input),
+                        output: core::mem::take(&mut connection.output),
+                    };
+                    connection.sent_continue = false;
+                    match self.serve_request(&mut stream, true) {
+                        Some(served) => {
+                            connection.closing = !served.keep_alive;
+                            served.write(&mut stream.output)?;
+                        }
+                        None => connection.closing = true,
+                    }
+                    connection.output = stream.output;
+                    continue;
+                }
+                BufferedRequest::Rejected(error) => {
+                    let mut response: HttpResponse<Box<dyn io::Read>> = error.into();
+                    response.add_header("Connection", "close");
+                    if self.send_date {
+                        add_date(&mut response.headers);
+                    }
+                    write_response(&mut connection.output, response, true)?;
+                    connection.closing = true;
+                    continue;
+                }
+            }
+
+            let mut buffer = [0; 8 * 1024];
+            match connection.stream.read(&mut buffer) {
+                // Any part of a request read so far is dropped along with the connection.
+                Ok(0) => return Ok(()),
+                Ok(n) => connection.input.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// The path parameters matched by a `Router` route, keyed by name without the leading `:`.
+pub type RouteParams = BTreeMap<String, String>;
+
+type RouteHandler = Box<
+    dyn FnMut(
+            &RouteParams,
+            &HttpHeaders,
+            &mut dyn io::Read,
+        ) -> crate::error::Result<HttpResponse<Box<dyn io::Read>>>
+        + Send,
+>;
+
+struct Route {
+    method: HttpMethod,
+    pattern: Vec<String>,
+    handler: RouteHandler,
+}
+
+/// The non-empty segments of the given path.
+fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// If the path matches the pattern, returns the values of its parameters.
+fn match_path(pattern: &[String], path: &str) -> Option<RouteParams> {
+    let mut params = RouteParams::new();
+    let mut segments = path_segments(path);
+    for p in pattern {
+        let segment = segments.next()?;
+        match p.strip_prefix(':') {
+            Some(name) => {
+                params.insert(name.into(), decode_component(segment).ok()?);
+            }
+            None if p == segment => {}
+            None => return None,
+        }
+    }
+    if segments.next().is_some() {
+        return None;
+    }
+    Some(params)
+}
+
+/// An `HttpRequestHandler` which dispatches requests to closures by their method and path.
+///
+/// Routes are registered with a path pattern like `/users/:id`, where a segment starting with `:`
+/// matches any one segment of the path. The closure is passed the matched segments by name, the
+/// request headers and the request body, which is empty for methods without one. Routes are tried
+/// in the order they were added, and requests matching none of them get `404 Not Found`. The
+/// pattern `*` matches the target of an `OPTIONS *` request.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle requests with the given method and a path matching `pattern` with `handler`.
+    pub fn route(
+        mut self,
+        method: HttpMethod,
+        pattern: &str,
+        handler: impl FnMut(
+                &RouteParams,
+                &HttpHeaders,
+                &mut dyn io::Read,
+            ) -> crate::error::Result<HttpResponse<Box<dyn io::Read>>>
+            + Send
+            + 'static,
+    ) -> Self {
+        self.routes.push(Route {
+            method,
+            pattern: path_segments(pattern).map(String::from).collect(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    fn handle(
+        &mut self,
+        method: HttpMethod,
+        uri: &str,
+        headers: &HttpHeaders,
+        body: &mut dyn io::Read,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read>>> {
+        let path = parse_request_target(uri)?.map_or("*".into(), |url| url.path().to_string());
+        for route in self.routes.iter_mut().filter(|r| r.method == method) {
+            if let Some(params) = match_path(&route.pattern, &path) {
+                return (route.handler)(&params, headers, body);
+            }
+        }
+        Ok(HttpResponse::from_string(HttpStatus::NotFound, "not found"))
+    }
+}
+
+impl<I: io::Read> HttpRequestHandler<I> for Router {
+    type Error = crate::error::Error;
+
+    fn connect<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Connect, &uri, headers, &mut io::empty())
+    }
+
+    fn delete<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Delete, &uri, headers, &mut io::empty())
+    }
+
+    fn get<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Get, &uri, headers, &mut io::empty())
+    }
+
+    fn head<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Head, &uri, headers, &mut io::empty())
+    }
+
+    fn options<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Options, &uri, headers, &mut io::empty())
+    }
+
+    fn patch<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Patch, &uri, headers, stream)
+    }
+
+    fn put<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Put, &uri, headers, stream)
+    }
+
+    fn post<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Post, &uri, headers, stream)
+    }
+
+    fn trace<'a>(
+        &'a mut self,
+        uri: String,
+        headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        self.handle(HttpMethod::Trace, &uri, headers, &mut io::empty())
+    }
+}
+
+/// An `HttpRequestHandler` which passes the method and target of every request to a closure, see
+/// `fn_handler`.
+pub struct FnHandler<F>(F);
+
+/// Wrap a closure in an `HttpRequestHandler`, for servers too small to need a handler type of
+/// their own. Every request is handled by calling `f` with its method and target. Request bodies
+/// are skipped over unread; use a `Router` for requests whose bodies matter.
+pub fn fn_handler<F>(f: F) -> FnHandler<F>
+where
+    F: FnMut(HttpMethod, String) -> crate::error::Result<HttpResponse<Box<dyn io::Read>>>,
+{
+    FnHandler(f)
+}
+
+impl<I: io::Read, F> HttpRequestHandler<I> for FnHandler<F>
+where
+    F: FnMut(HttpMethod, String) -> crate::error::Result<HttpResponse<Box<dyn io::Read>>>,
+{
+    type Error = crate::error::Error;
+
+    fn connect<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Connect, uri)
+    }
+
+    fn delete<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Delete, uri)
+    }
+
+    fn get<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Get, uri)
+    }
+
+    fn head<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Head, uri)
+    }
+
+    fn options<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Options, uri)
+    }
+
+    fn patch<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Patch, uri)
+    }
+
+    fn put<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Put, uri)
+    }
+
+    fn post<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+        _stream: &mut HttpBody<&mut I>,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Post, uri)
+    }
+
+    fn trace<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> crate::error::Result<HttpResponse<Box<dyn io::Read + 'a>>> {
+        (self.0)(HttpMethod::Trace, uri)
+    }
+}
+
+#[cfg(test)]
+#[derive(PartialEq, Debug)]
+pub struct ExpectedRequest {
+    pub expected_method: HttpMethod,
+    pub expected_uri: String,
+    pub expected_body: String,
+
+    pub response_status: HttpStatus,
     pub response_body: String,
     pub response_headers: HttpHeaders,
 }
@@ -281,9 +1757,27 @@ use std::io::Read;
 impl<I: io::Read> HttpRequestHandler<I> for TestRequestHandler {
     type Error = HttpResponse<Box<dyn io::Read>>;
 
+    fn delete<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        let request = self.script.remove(0);
+        assert_eq!(request.expected_method, HttpMethod::Delete);
+        assert_eq!(request.expected_uri, uri);
+
+        let mut res = HttpResponse::from_string(request.response_status, request.response_body);
+        for (k, v) in &request.response_headers {
+            res.add_header(k, v.clone());
+        }
+
+        Ok(res)
+    }
+
     fn get<'a>(
         &'a mut self,
         uri: String,
+        _headers: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         let request = self.script.remove(0);
         assert_eq!(request.expected_method, HttpMethod::Get);
@@ -297,10 +1791,33 @@ impl<I: io::Read> HttpRequestHandler<I> for TestRequestHandler {
         Ok(res)
     }
 
+    fn post<'a>(
+        &'a mut self,
+        uri: String,
+        _headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        let request = self.script.remove(0);
+        assert_eq!(request.expected_method, HttpMethod::Post);
+        assert_eq!(request.expected_uri, uri);
+
+        let mut body_string = String::new();
+        stream.read_to_string(&mut body_string).unwrap();
+        assert_eq!(request.expected_body, body_string);
+
+        let mut res = HttpResponse::from_string(request.response_status, request.response_body);
+        for (k, v) in &request.response_headers {
+            res.add_header(k, v.clone());
+        }
+
+        Ok(res)
+    }
+
     fn put<'a>(
         &'a mut self,
         uri: String,
-        mut stream: HttpBody<&mut I>,
+        _headers: &HttpHeaders,
+        stream: &mut HttpBody<&mut I>,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
         let request = self.script.remove(0);
         assert_eq!(request.expected_method, HttpMethod::Put);
@@ -310,10 +1827,12 @@ impl<I: io::Read> HttpRequestHandler<I> for TestRequestHandler {
         stream.read_to_string(&mut body_string).unwrap();
         assert_eq!(request.expected_body, body_string);
 
-        Ok(HttpResponse::from_string(
-            request.response_status,
-            request.response_body,
-        ))
+        let mut res = HttpResponse::from_string(request.response_status, request.response_body);
+        for (k, v) in &request.response_headers {
+            res.add_header(k, v.clone());
+        }
+
+        Ok(res)
     }
 }
 
@@ -336,7 +1855,7 @@ pub fn test_server(
     Ok((server_address.port(), server))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "ssl"))]
 pub fn test_ssl_server(
     key_file: &str,
     cert_file: &str,
@@ -363,14 +1882,39 @@ pub fn test_ssl_server(
     Ok((server_address.port(), server))
 }
 
+/// Like `test_ssl_server`, but clients must present a certificate signed by the test CA.
+#[cfg(all(test, feature = "ssl", not(feature = "native-tls")))]
+pub fn test_ssl_server_with_client_auth(
+    script: Vec<ExpectedRequest>,
+) -> crate::error::Result<(
+    u16,
+    HttpServer<crate::ssl::SslListener<std::net::TcpListener>, TestRequestHandler>,
+)> {
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let server_address = server_socket.local_addr()?;
+    let handler = TestRequestHandler::new(script);
+
+    let manifest_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let stream = crate::ssl::SslListener::with_client_auth(
+        &std::fs::read(manifest_dir.join("test_key.pem"))?,
+        &std::fs::read(manifest_dir.join("test_cert.pem"))?,
+        &std::fs::read(manifest_dir.join("test_ca.pem"))?,
+        server_socket,
+    )?;
+    let server = HttpServer::new(stream, handler);
+
+    Ok((server_address.port(), server))
+}
+
 #[cfg(test)]
+#[derive(Clone)]
 pub struct LendingHandler {
     body_data: Vec<u8>,
 }
 
 #[cfg(test)]
 impl LendingHandler {
-    fn new(body_data: Vec<u8>) -> Self {
+    pub(crate) fn new(body_data: Vec<u8>) -> Self {
         Self { body_data }
     }
 }
@@ -382,22 +1926,28 @@ impl<I: io::Read> HttpRequestHandler<I> for LendingHandler {
     fn get<'a>(
         &'a mut self,
         _: String,
+        _: &HttpHeaders,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
-        Ok(HttpResponse::new(
+        let mut res = HttpResponse::new(
             HttpStatus::OK,
-            Box::new(&self.body_data[..]),
-        ))
+            Box::new(&self.body_data[..]) as Box<dyn io::Read + 'a>,
+        );
+        res.add_header("Content-Length", self.body_data.len().to_string());
+        Ok(res)
     }
 
     fn put<'a>(
         &'a mut self,
         _: String,
-        _: HttpBody<&mut I>,
+        _: &HttpHeaders,
+        _: &mut HttpBody<&mut I>,
     ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
-        Ok(HttpResponse::new(
+        let mut res = HttpResponse::new(
             HttpStatus::OK,
-            Box::new(&self.body_data[..]),
-        ))
+            Box::new(&self.body_data[..]) as Box<dyn io::Read + 'a>,
+        );
+        res.add_header("Content-Length", self.body_data.len().to_string());
+        Ok(res)
     }
 }
 
@@ -417,3 +1967,1327 @@ fn server_handler_can_lend_to_stream() {
 
     assert_eq!(res_data, b"hello world");
 }
+
+#[test]
+fn server_keep_alive() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = LendingHandler::new(b"hello world"[..].into());
+    let mut server = HttpServer::new(server_socket.try_clone().unwrap(), handler);
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = server_socket.accept()?;
+        server.serve_connection(stream)
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let mut client = crate::client::HttpClient::<std::net::TcpStream>::new();
+    for _ in 0..2 {
+        // The handler ignores the body, so the server has to skip it to find the next request.
+        let mut request = client.put(&url[..]).unwrap();
+        io::copy(&mut &b"ignored body"[..], &mut request).unwrap();
+        let mut res_data = vec![];
+        request
+            .finish()
+            .unwrap()
+            .body
+            .read_to_end(&mut res_data)
+            .unwrap();
+        assert_eq!(res_data, b"hello world");
+
+        let mut res_data = vec![];
+        let mut response = client.get(&url[..]).unwrap().finish().unwrap();
+        response.body.read_to_end(&mut res_data).unwrap();
+        assert_eq!(res_data, b"hello world");
+    }
+
+    // Dropping the client closes the connection, which ends `serve_connection`.
+    drop(client);
+    handle.join().unwrap().unwrap();
+}
+
+/// Responds with its body by reference, so responses are never boxed.
+#[cfg(test)]
+struct UnboxedHandler {
+    body: Vec<u8>,
+}
+
+#[cfg(test)]
+impl HttpHandler<std::net::TcpStream> for UnboxedHandler {
+    type Body<'a> = &'a [u8];
+    type Error = crate::error::Error;
+
+    fn handle<'a>(
+        &'a mut self,
+        method: HttpMethod,
+        _uri: String,
+        _headers: &HttpHeaders,
+        body: Option<&mut HttpBody<&mut std::net::TcpStream>>,
+    ) -> crate::error::Result<HttpResponse<&'a [u8]>> {
+        if let Some(body) = body {
+            self.body.clear();
+            body.read_to_end(&mut self.body)?;
+        }
+        let status = match method {
+            HttpMethod::Get | HttpMethod::Put => HttpStatus::OK,
+            _ => HttpStatus::MethodNotAllowed,
+        };
+        let length = self.body.len() as u64;
+        Ok(HttpResponse::new(status, &self.body[..]).with_length(length))
+    }
+}
+
+#[cfg(test)]
+struct EarlyHintsHandler;
+
+#[cfg(test)]
+impl HttpHandler<MemoryStream> for EarlyHintsHandler {
+    type Body<'a> = &'static [u8];
+    type Error = crate::error::Error;
+
+    fn handle(
+        &mut self,
+        _method: HttpMethod,
+        _uri: String,
+        _headers: &HttpHeaders,
+        _body: Option<&mut HttpBody<&mut MemoryStream>>,
+    ) -> crate::error::Result<HttpResponse<&'static [u8]>> {
+        Ok(HttpResponse::new(HttpStatus::OK, &b"page"[..]).with_length(4))
+    }
+
+    fn handle_with_interim(
+        &mut self,
+        method: HttpMethod,
+        uri: String,
+        headers: &HttpHeaders,
+        interim: &mut Interim<'_, '_, MemoryStream>,
+    ) -> crate::error::Result<HttpResponse<&'static [u8]>> {
+        assert!(interim
+            .send(HttpStatus::OK, &crate::http_headers! {})
+            .is_err());
+        let hints = crate::http_headers! { "Link" => "</style.css>; rel=preload" };
+        interim.send(HttpStatus::EarlyHints, &hints)?;
+        self.handle(method, uri, headers, interim.body())
+    }
+}
+
+#[test]
+fn server_sends_early_hints() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, EarlyHintsHandler);
+    server.set_send_date(false);
+
+    let mut client = listener.connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    server.serve_one().unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 103 Early Hints\r\n\
+         Link: </style.css>; rel=preload\r\n\
+         \r\n\
+         HTTP/1.1 200 OK\r\n\
+         Connection: close\r\n\
+         Content-Length: 4\r\n\
+         \r\n\
+         page"
+    );
+
+    let mut client = listener.connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.interim_responses()[0].0, HttpStatus::EarlyHints);
+    assert_eq!(response.into_string().unwrap(), "page");
+}
+
+#[test]
+fn server_no_early_hints_for_http_1_0() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, EarlyHintsHandler);
+    server.set_send_date(false);
+
+    let mut client = listener.connect();
+    client.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    server.serve_one().unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+         Connection: close\r\n\
+         Content-Length: 4\r\n\
+         \r\n\
+         page"
+    );
+}
+
+#[test]
+fn server_unboxed_handler_many_requests() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = UnboxedHandler {
+        body: b"hello world".to_vec(),
+    };
+    let mut server = HttpServer::new(server_socket.try_clone().unwrap(), handler);
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = Listen::accept(&server_socket)?;
+        server.serve_connection(stream)
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let mut client = crate::client::HttpClient::<std::net::TcpStream>::new();
+    for _ in 0..1000 {
+        let response = client.get(&url[..]).unwrap().finish().unwrap();
+        assert_eq!(response.status, HttpStatus::OK);
+        assert_eq!(response.into_bytes().unwrap(), b"hello world");
+    }
+
+    let mut request = client.put(&url[..]).unwrap();
+    io::copy(&mut &b"new body"[..], &mut request).unwrap();
+    assert_eq!(request.finish().unwrap().into_bytes().unwrap(), b"new body");
+    let response = client.delete(&url[..]).unwrap().finish().unwrap();
+    assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+    response.into_bytes().unwrap();
+
+    drop(client);
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn memory_listener_serve_one() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, LendingHandler::new(b"stored"[..].into()));
+    server.set_send_date(false);
+    assert!(matches!(
+        server.serve_one().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    ));
+
+    let mut client = listener.connect();
+    client
+        .write_all(b"PUT /a HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\r\nhello")
+        .unwrap();
+    server.serve_one().unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 6\r\n\r\nstored"
+    );
+}
+
+#[test]
+fn server_sends_date() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, LendingHandler::new(b"hi"[..].into()));
+
+    let before = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    let mut client = listener.connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    let date = crate::date::parse_http_date(response.get_header("Date").unwrap()).unwrap();
+    assert!(date >= before && date <= std::time::SystemTime::now());
+
+    server.set_send_date(false);
+    let mut client = listener.connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.get_header("Date"), None);
+}
+
+#[test]
+fn server_not_modified_when_etag_matches() {
+    use crate::protocol::{etag_matches, weak_etag};
+    use std::io::Write as _;
+
+    let etag = weak_etag(5, std::time::UNIX_EPOCH);
+    let route_etag = etag.clone();
+    let router = Router::new().route(HttpMethod::Get, "/file", move |_, headers, _| {
+        let mut response = match headers.get("If-None-Match") {
+            Some(if_none_match) if etag_matches(if_none_match, &route_etag) => HttpResponse::new(
+                HttpStatus::NotModified,
+                Box::new(io::empty()) as Box<dyn io::Read>,
+            ),
+            _ => HttpResponse::from_string(HttpStatus::OK, "hello"),
+        };
+        response.add_header("ETag", route_etag.clone());
+        Ok(response)
+    });
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, router);
+
+    let mut client = listener.connect();
+    write!(
+        client,
+        "GET /file HTTP/1.1\r\nHost: test\r\nIf-None-Match: \"other\", {}\r\n\r\n",
+        etag
+    )
+    .unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.status, HttpStatus::NotModified);
+    assert_eq!(response.get_header("ETag"), Some(&etag[..]));
+    assert_eq!(response.into_string().unwrap(), "");
+
+    let mut client = listener.connect();
+    client
+        .write_all(b"GET /file HTTP/1.1\r\nHost: test\r\nIf-None-Match: \"other\"\r\n\r\n")
+        .unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "hello");
+}
+
+/// A non-blocking stream fed by a test. Reads would block while there is nothing to read, and
+/// writes once more than `write_limit` bytes have been written.
+#[cfg(test)]
+struct TrickleStream {
+    input: Vec<u8>,
+    closed: bool,
+    output: Vec<u8>,
+    write_limit: usize,
+}
+
+#[cfg(test)]
+impl TrickleStream {
+    fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            closed: false,
+            output: Vec::new(),
+            write_limit: usize::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+impl io::Read for TrickleStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.input.is_empty() && !self.closed {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = std::cmp::min(buf.len(), self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+impl io::Write for TrickleStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = std::cmp::min(buf.len(), self.write_limit - self.output.len());
+        if n == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.output.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn echo_router() -> Router {
+    Router::new()
+        .route(HttpMethod::Get, "/", |_, _, _| {
+            Ok(HttpResponse::from_string(HttpStatus::OK, "hi"))
+        })
+        .route(HttpMethod::Put, "/echo", |_, _, body| {
+            let mut s = String::new();
+            body.read_to_string(&mut s)?;
+            Ok(HttpResponse::from_string(HttpStatus::OK, s))
+        })
+}
+
+#[test]
+fn serve_nonblocking_fragments() {
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(listener, echo_router());
+    server.set_send_date(false);
+    let mut connection = NonBlockingConnection::new(TrickleStream::new());
+
+    let request =
+        b"PUT /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+    for &byte in &request[..] {
+        let error = server.serve_nonblocking(&mut connection).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(connection.get_ref().output.is_empty());
+        connection.stream.input.push(byte);
+    }
+    let error = server.serve_nonblocking(&mut connection).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(
+        std::str::from_utf8(&connection.get_ref().output).unwrap(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+    );
+
+    // The connection is kept alive for the next request.
+    connection.stream.output.clear();
+    connection.stream.input.extend_from_slice(b"GET / HT");
+    let error = server.serve_nonblocking(&mut connection).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    connection
+        .stream
+        .input
+        .extend_from_slice(b"TP/1.1\r\nHost: test\r\n\r\n");
+    let error = server.serve_nonblocking(&mut connection).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(
+        std::str::from_utf8(&connection.get_ref().output).unwrap(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi"
+    );
+
+    connection.stream.closed = true;
+    server.serve_nonblocking(&mut connection).unwrap();
+}
+
+#[test]
+fn serve_nonblocking_pipelined() {
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(listener, echo_router());
+    server.set_send_date(false);
+    let mut connection = NonBlockingConnection::new(TrickleStream::new());
+
+    connection.stream.input.extend_from_slice(
+        b"PUT /echo HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\r\nhello\
+          PUT /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n\
+          3\r\nbye\r\n0\r\n\r\n\
+          GET / HTTP/1.1\r\nHost: test\r\n\r\n",
+    );
+    let error = server.serve_nonblocking(&mut connection).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(
+        std::str::from_utf8(&connection.get_ref().output).unwrap(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello\
+         HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nbye\
+         HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi"
+    );
+}
+
+#[test]
+fn serve_nonblocking_rejects_large_requests() {
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(listener, echo_router());
+    server.set_send_date(false);
+
+    let too_large = [
+        format!(
+            "PUT /echo HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n",
+            MAX_BUFFERED_BODY + 1
+        ),
+        format!(
+            "PUT /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n",
+            MAX_BUFFERED_BODY + 1
+        ),
+        format!(
+            "GET / HTTP/1.1\r\nHost: test\r\nX-Long: {}",
+            "a".repeat(MAX_BUFFERED_HEAD)
+        ),
+    ];
+    for (request, status) in too_large.iter().zip([
+        HttpStatus::RequestEntityTooLarge,
+        HttpStatus::RequestEntityTooLarge,
+        HttpStatus::RequestHeaderFieldsTooLarge,
+    ]) {
+        let mut connection = NonBlockingConnection::new(TrickleStream::new());
+        connection
+            .stream
+            .input
+            .extend_from_slice(request.as_bytes());
+        server.serve_nonblocking(&mut connection).unwrap();
+        let output = connection.into_inner().output;
+        let response = HttpResponse::deserialize(&output[..]).unwrap();
+        assert_eq!(response.status, status);
+        assert_eq!(response.get_header("Connection"), Some("close"));
+    }
+}
+
+#[test]
+fn serve_nonblocking_continue_and_slow_writes() {
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(listener, echo_router());
+    server.set_send_date(false);
+    let mut connection = NonBlockingConnection::new(TrickleStream::new());
+
+    // The client waits to be told to continue before sending the body.
+    connection.stream.input.extend_from_slice(
+        b"PUT /echo HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\nExpect: 100-continue\r\nConnection: close\r\n\r\n",
+    );
+    let error = server.serve_nonblocking(&mut connection).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(
+        std::str::from_utf8(&connection.get_ref().output).unwrap(),
+        "HTTP/1.1 100 Continue\r\n\r\n"
+    );
+
+    // The response is written as the stream takes it.
+    connection.stream.output.clear();
+    connection.stream.write_limit = 0;
+    connection.stream.input.extend_from_slice(b"hello");
+    loop {
+        match server.serve_nonblocking(&mut connection) {
+            Ok(()) => break,
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+                assert!(connection.wants_write());
+                connection.stream.write_limit += 10;
+            }
+        }
+    }
+    assert!(!connection.wants_write());
+    let output = connection.into_inner().output;
+    let response = HttpResponse::deserialize(&output[..]).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "hello");
+}
+
+#[test]
+fn server_echoes_trace() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, LendingHandler::new(b"hi"[..].into()));
+    let request = b"TRACE /a?b HTTP/1.1\r\nHost: h\r\nCookie: secret\r\nX-Test: 1\r\n\r\n";
+
+    // TRACE isn't echoed unless asked for.
+    let mut client = listener.connect();
+    client.write_all(request).unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+
+    server.set_echo_trace(true);
+    let mut client = listener.connect();
+    client.write_all(request).unwrap();
+    server.serve_one().unwrap();
+    let response = HttpResponse::deserialize(client).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.get_header("Content-Type"), Some("message/http"));
+    assert_eq!(
+        response.into_string().unwrap(),
+        "TRACE /a?b HTTP/1.1\r\nHost: h\r\nX-Test: 1\r\n\r\n"
+    );
+}
+
+#[test]
+fn server_rejects_transfer_encoding_with_content_length() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut server = HttpServer::new(&listener, LendingHandler::new(b"stored"[..].into()));
+    let mut client = listener.connect();
+    client
+        .write_all(
+            b"POST / HTTP/1.1\r\nHost: test\r\n\
+              Content-Length: 4\r\n\
+              Transfer-Encoding: chunked\r\n\r\n\
+              0\r\n\r\nGET /smuggled HTTP/1.1\r\nHost: test\r\n\r\n",
+        )
+        .unwrap();
+    server.serve_one().unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+}
+
+#[test]
+fn server_rejects_malformed_request_line() {
+    use std::io::Write as _;
+
+    for request_line in [
+        "GARBAGE",
+        "GET",
+        "GET /",
+        "GET / HTTP/x.y",
+        "GET / HTTP/1",
+        "FROB / HTTP/1.1",
+    ] {
+        let listener = MemoryListener::new();
+        let mut server = HttpServer::new(&listener, echo_router());
+        let mut client = listener.connect();
+        write!(client, "{}\r\n\r\n", request_line).unwrap();
+        server.serve_one().unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "{}: {}",
+            request_line,
+            response
+        );
+    }
+}
+
+#[test]
+fn memory_listener_serve_connection() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut client = listener.connect();
+    let (stream, _) = listener.accept().unwrap();
+    let mut server = HttpServer::new(listener, LendingHandler::new(b"hi"[..].into()));
+    server.set_send_date(false);
+    let handle = std::thread::spawn(move || server.serve_connection(stream));
+
+    let expected = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+    for _ in 0..2 {
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+            .unwrap();
+        let mut response = vec![0; expected.len()];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(std::str::from_utf8(&response).unwrap(), expected);
+    }
+
+    // Shutting down the client's end ends `serve_connection`.
+    client.shutdown_write();
+    handle.join().unwrap().unwrap();
+    let mut rest = vec![];
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[cfg(test)]
+fn failing_router() -> Router {
+    Router::new()
+        .route(HttpMethod::Get, "/", |_, _, _| {
+            Ok(HttpResponse::from_string(HttpStatus::OK, "hi"))
+        })
+        .route(HttpMethod::Put, "/fail", |_, _, _| {
+            Err(crate::error::Error::Other("handler failed".into()))
+        })
+}
+
+#[test]
+fn server_skips_body_after_handler_error() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut client = listener.connect();
+    let (stream, _) = listener.accept().unwrap();
+    let mut server = HttpServer::new(listener, failing_router());
+    server.set_send_date(false);
+    let handle = std::thread::spawn(move || server.serve_connection(stream));
+
+    // The handler fails without reading the body, which has to be skipped to find the next
+    // request on the connection.
+    let requests: [(&[u8], &str); 2] = [
+        (
+            b"PUT /fail HTTP/1.1\r\nHost: test\r\nContent-Length: 11\r\n\r\nunread body",
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 14\r\n\r\nhandler failed",
+        ),
+        (
+            b"GET / HTTP/1.1\r\nHost: test\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+        ),
+    ];
+    for (request, expected) in requests {
+        client.write_all(request).unwrap();
+        let mut response = vec![0; expected.len()];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(std::str::from_utf8(&response).unwrap(), expected);
+    }
+
+    client.shutdown_write();
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_closes_connection_instead_of_skipping_large_body() {
+    use std::io::Write as _;
+
+    let listener = MemoryListener::new();
+    let mut client = listener.connect();
+    let (stream, _) = listener.accept().unwrap();
+    let mut server = HttpServer::new(listener, failing_router());
+    server.set_send_date(false);
+    server.set_max_body_size(Some(4));
+    let handle = std::thread::spawn(move || server.serve_connection(stream));
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: test\r\nContent-Length: 11\r\n\r\nunread body")
+        .unwrap();
+    handle.join().unwrap().unwrap();
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nhi"
+    );
+}
+
+#[test]
+fn server_closes_connection_when_asked() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handler = LendingHandler::new(b"hello world"[..].into());
+    let mut server = HttpServer::new(server_socket.try_clone().unwrap(), handler);
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = server_socket.accept()?;
+        server.serve_connection(stream)
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let stream = std::net::TcpStream::connect(server_address).unwrap();
+    let response = crate::client::HttpRequestBuilder::get(&url[..])
+        .unwrap()
+        .add_header("Connection", "close")
+        .send(stream)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.get_header("Connection"), Some("close"));
+    handle.join().unwrap().unwrap();
+}
+
+/// Responds with a body whose length isn't known up front.
+#[cfg(test)]
+pub struct UnknownLengthHandler;
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for UnknownLengthHandler {
+    type Error = HttpResponse<Box<dyn io::Read>>;
+
+    fn get<'a>(
+        &'a mut self,
+        _: String,
+        _: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        let body = io::Read::chain(&b"hello "[..], &b"world"[..]);
+        Ok(HttpResponse::new(HttpStatus::OK, Box::new(body)))
+    }
+}
+
+#[test]
+fn server_chunks_unknown_length_body() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, UnknownLengthHandler);
+    let handle = std::thread::spawn(move || {
+        server.serve_one().unwrap();
+        server.serve_one().unwrap();
+    });
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let stream = std::net::TcpStream::connect(server_address).unwrap();
+    let response = crate::client::HttpRequestBuilder::get(&url[..])
+        .unwrap()
+        .send(stream)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(response.get_header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(response.into_string().unwrap(), "hello world");
+
+    let mut body = crate::client::get(&url[..]).unwrap();
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str).unwrap();
+    assert_eq!(body_str, "hello world");
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn server_does_not_chunk_for_http_1_0() {
+    use std::io::Write as _;
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, UnknownLengthHandler);
+    let handle = std::thread::spawn(move || server.serve_one().unwrap());
+
+    let mut stream = std::net::TcpStream::connect(server_address).unwrap();
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    handle.join().unwrap();
+
+    assert!(!response.contains("Transfer-Encoding"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nhello world"), "{}", response);
+}
+
+#[test]
+fn expect_continue_request_gets_body_sent() {
+    use std::io::Write as _;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/upload".into(),
+        expected_body: "the body".into(),
+        response_status: HttpStatus::OK,
+        response_body: "stored".into(),
+        response_headers: Default::default(),
+    }])
+    .unwrap();
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://localhost:{}/upload", port);
+    let stream = std::net::TcpStream::connect(("localhost", port)).unwrap();
+    let mut request = crate::client::HttpRequestBuilder::put(&url[..])
+        .unwrap()
+        .add_header("Expect", "100-continue")
+        .send(stream)
+        .unwrap();
+    assert!(matches!(
+        request,
+        crate::protocol::OutgoingRequest::WithBody(_)
+    ));
+    request.write_all(b"the body").unwrap();
+    let response = request.finish().unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "stored");
+}
+
+#[test]
+fn expect_continue_request_rejected_before_body() {
+    use std::io::{BufRead as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = server_socket.accept().unwrap();
+        let mut reader = std::io::BufReader::new(stream);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let mut stream = reader.into_inner();
+        stream
+            .write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 4\r\n\r\nnope")
+            .unwrap();
+    });
+
+    let url = format!("http://localhost:{}/upload", server_address.port());
+    let stream = std::net::TcpStream::connect(server_address).unwrap();
+    let mut request = crate::client::HttpRequestBuilder::put(&url[..])
+        .unwrap()
+        .add_header("Expect", "100-continue")
+        .send(stream)
+        .unwrap();
+    assert!(matches!(
+        request,
+        crate::protocol::OutgoingRequest::Rejected(_)
+    ));
+    request.write_all(b"never sent").unwrap();
+    let response = request.finish().unwrap();
+    handle.join().unwrap();
+
+    assert_eq!(response.status, HttpStatus::Unauthorized);
+    assert_eq!(response.into_string().unwrap(), "nope");
+}
+
+/// Responds with the IP address of the client.
+#[cfg(test)]
+#[derive(Default)]
+pub struct PeerAddrHandler {
+    peer_addr: Option<SocketAddr>,
+}
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for PeerAddrHandler {
+    type Error = HttpResponse<Box<dyn io::Read>>;
+
+    fn accepted(&mut self, peer_addr: Option<SocketAddr>) {
+        self.peer_addr = peer_addr;
+    }
+
+    fn get<'a>(
+        &'a mut self,
+        _: String,
+        _: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        let ip = self.peer_addr.map(|a| a.ip().to_string());
+        Ok(HttpResponse::from_string(
+            HttpStatus::OK,
+            ip.unwrap_or_default(),
+        ))
+    }
+}
+
+#[test]
+fn server_handler_sees_peer_addr() {
+    let server_socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, PeerAddrHandler::default());
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://127.0.0.1:{}/", server_address.port());
+    let mut body = crate::client::get(&url[..]).unwrap();
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str).unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(body_str, "127.0.0.1");
+}
+
+#[test]
+fn server_serve_until_shutdown() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello".into(),
+        response_headers: Default::default(),
+    }])
+    .unwrap();
+    server.connection_stream.set_nonblocking(true).unwrap();
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let server_shutdown = shutdown.clone();
+    let handle =
+        std::thread::spawn(move || server.serve_until(|| server_shutdown.load(Ordering::SeqCst)));
+
+    let url = format!("http://localhost:{}/", port);
+    let mut body = crate::client::get(&url[..]).unwrap();
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str).unwrap();
+    assert_eq!(body_str, "hello");
+
+    shutdown.store(true, Ordering::SeqCst);
+    handle.join().unwrap();
+}
+
+#[test]
+fn server_head_from_get() {
+    use std::io::{Read as _, Write as _};
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/file".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "file contents".into(),
+        response_headers: crate::http_headers! { "Content-Type" => "text/plain" },
+    }])
+    .unwrap();
+    server.set_head_from_get(true);
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let mut stream = std::net::TcpStream::connect(("localhost", port)).unwrap();
+    write!(stream, "HEAD /file HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(
+        response.contains("Content-Type: text/plain\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("Content-Length: 13\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\n"), "{}", response);
+}
+
+#[test]
+fn server_rejects_body_over_max_size() {
+    use std::io::{Read as _, Write as _};
+
+    let router = Router::new().route(HttpMethod::Put, "/", |_, _, body| {
+        io::copy(body, &mut io::sink())?;
+        Ok(HttpResponse::from_string(HttpStatus::OK, "stored"))
+    });
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, router);
+    server.set_max_body_size(Some(10));
+    let handle = std::thread::spawn(move || {
+        for _ in 0..4 {
+            server.serve_one().unwrap();
+        }
+    });
+
+    let requests = [
+        ("Content-Length: 10\r\n", "0123456789", "200 OK"),
+        (
+            "Content-Length: 11\r\n",
+            "0123456789a",
+            "413 Request Entity Too Large",
+        ),
+        (
+            "Transfer-Encoding: chunked\r\n",
+            "5\r\n01234\r\n5\r\n56789\r\n0\r\n\r\n",
+            "200 OK",
+        ),
+        (
+            "Transfer-Encoding: chunked\r\n",
+            "5\r\n01234\r\n6\r\n56789a\r\n0\r\n\r\n",
+            "413 Request Entity Too Large",
+        ),
+    ];
+    for (header, body, status) in &requests {
+        let mut stream = std::net::TcpStream::connect(server_address).unwrap();
+        write!(
+            stream,
+            "PUT / HTTP/1.1\r\nHost: test\r\n{}\r\n{}",
+            header, body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {}\r\n", status)),
+            "{}",
+            response
+        );
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn server_options_asterisk() {
+    use std::io::{Read as _, Write as _};
+
+    let router = Router::new().route(HttpMethod::Options, "*", |_, _, _| {
+        let mut response = HttpResponse::from_string(HttpStatus::OK, "");
+        response.add_header("Allow", "GET, OPTIONS");
+        Ok(response)
+    });
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, router);
+    let handle = std::thread::spawn(move || {
+        server.serve_one().unwrap();
+        server.serve_one().unwrap();
+    });
+
+    let request = |request_line: &str| {
+        let mut stream = std::net::TcpStream::connect(server_address).unwrap();
+        write!(stream, "{}\r\nHost: localhost\r\n\r\n", request_line).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = request("OPTIONS * HTTP/1.1");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(
+        response.contains("\r\nAllow: GET, OPTIONS\r\n"),
+        "{}",
+        response
+    );
+
+    let response = request("GET * HTTP/1.1");
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{}",
+        response
+    );
+    handle.join().unwrap();
+}
+
+#[test]
+fn router_matches_path_params() {
+    use std::io::Read as _;
+
+    let router = Router::new()
+        .route(HttpMethod::Get, "/users", |_, _, _| {
+            Ok(HttpResponse::from_string(HttpStatus::OK, "all users"))
+        })
+        .route(HttpMethod::Get, "/users/:id", |params, _, _| {
+            Ok(HttpResponse::from_string(
+                HttpStatus::OK,
+                format!("user {}", params["id"]),
+            ))
+        })
+        .route(HttpMethod::Post, "/users/:id", |params, _, body| {
+            let mut body_str = String::new();
+            body.read_to_string(&mut body_str)?;
+            Ok(HttpResponse::from_string(
+                HttpStatus::Created,
+                format!("{} for user {}", body_str, params["id"]),
+            ))
+        });
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+    let mut server = HttpServer::new(server_socket, router);
+    let handle = std::thread::spawn(move || {
+        for _ in 0..4 {
+            server.serve_one().unwrap();
+        }
+    });
+
+    let request = |method, path: &str, body: &str| {
+        let url = format!("http://localhost:{}{}", port, path);
+        let mut response = crate::client::request(method, &url[..], body.as_bytes()).unwrap();
+        let mut body_str = String::new();
+        response.body.read_to_string(&mut body_str).unwrap();
+        (response.status, body_str)
+    };
+    assert_eq!(
+        request(HttpMethod::Get, "/users/4%202?x=y", ""),
+        (HttpStatus::OK, "user 4 2".into())
+    );
+    assert_eq!(
+        request(HttpMethod::Get, "/users", ""),
+        (HttpStatus::OK, "all users".into())
+    );
+    assert_eq!(
+        request(HttpMethod::Post, "/users/7", "a post"),
+        (HttpStatus::Created, "a post for user 7".into())
+    );
+    assert_eq!(
+        request(HttpMethod::Get, "/users/42/posts", "").0,
+        HttpStatus::NotFound
+    );
+    handle.join().unwrap();
+}
+
+/// Waits in every request for the given number of requests to be in progress at once.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct BarrierHandler(std::sync::Arc<std::sync::Barrier>);
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for BarrierHandler {
+    type Error = HttpResponse<Box<dyn io::Read>>;
+
+    fn get<'a>(
+        &'a mut self,
+        uri: String,
+        _: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        if uri == "/panic" {
+            panic!("handler panicked");
+        }
+        self.0.wait();
+        Ok(HttpResponse::from_string(HttpStatus::OK, uri))
+    }
+}
+
+#[test]
+fn server_threaded_serves_concurrently() {
+    const NUM_REQUESTS: usize = 4;
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(NUM_REQUESTS));
+    let mut server = HttpServer::new(server_socket, BarrierHandler(barrier));
+    let (errors, reported) = std::sync::mpsc::channel();
+    server.set_error_callback(move |e| errors.send(e.to_string()).unwrap());
+    std::thread::spawn(move || server.serve_forever_threaded(NUM_REQUESTS));
+
+    // A panicking handler mustn't take a thread out of service.
+    let url = format!("http://localhost:{}/panic", server_address.port());
+    assert!(crate::client::get(&url[..]).is_err());
+    assert_eq!(reported.recv().unwrap(), "request handler panicked");
+
+    let clients: Vec<_> = (0..NUM_REQUESTS)
+        .map(|i| {
+            let url = format!("http://localhost:{}/{}", server_address.port(), i);
+            std::thread::spawn(move || {
+                let mut client = crate::client::HttpClient::<std::net::TcpStream>::new();
+                client
+                    .get(&url[..])
+                    .unwrap()
+                    .finish()
+                    .unwrap()
+                    .into_string()
+            })
+        })
+        .collect();
+    for (i, client) in clients.into_iter().enumerate() {
+        assert_eq!(client.join().unwrap().unwrap(), format!("/{}", i));
+    }
+}
+
+#[test]
+fn server_threaded_rejects_when_busy() {
+    use std::io::Write as _;
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    // The handler and the test meet at the barrier, so the test knows when a request is handled.
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+    let mut server = HttpServer::new(server_socket, BarrierHandler(barrier.clone()));
+    server.set_reject_when_busy(true);
+    std::thread::spawn(move || server.serve_forever_threaded(1));
+
+    // The one thread is busy with this connection for as long as it is kept open.
+    let mut busy = std::net::TcpStream::connect(server_address).unwrap();
+    busy.write_all(b"GET /busy HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    barrier.wait();
+    let response = HttpResponse::deserialize(&mut busy).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "/busy");
+
+    let mut rejected = std::net::TcpStream::connect(server_address).unwrap();
+    let mut response = String::new();
+    rejected.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "{}",
+        response
+    );
+
+    // Once the connection is done, the thread is free for the next one. Connections are still
+    // rejected until the thread notices it is closed.
+    drop(busy);
+    let url = format!("http://localhost:{}/free", server_address.port());
+    let client = std::thread::spawn(move || loop {
+        let response = crate::client::request(HttpMethod::Get, &url[..], io::empty())?;
+        if response.status != HttpStatus::ServiceUnavailable {
+            return response.into_string();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    });
+    barrier.wait();
+    assert_eq!(client.join().unwrap().unwrap(), "/free");
+}
+
+#[test]
+fn server_rejects_oversized_headers() {
+    use std::io::{Read as _, Write as _};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let limits = HeaderLimits {
+        max_line_length: 1024,
+        max_total_size: 4096,
+    };
+    let mut server = HttpServer::with_limits(server_socket, EchoHeaderHandler, limits);
+    let handle = std::thread::spawn(move || {
+        server.serve_one().unwrap();
+        server.serve_one().unwrap();
+    });
+
+    let requests = [
+        // One very long header line
+        format!(
+            "GET / HTTP/1.1\r\nHost: test\r\nX-Echo: {}\r\n\r\n",
+            "a".repeat(2000)
+        ),
+        // Many short header lines
+        format!(
+            "GET / HTTP/1.1\r\nHost: test\r\n{}\r\n",
+            "X-Echo: a\r\n".repeat(500)
+        ),
+    ];
+    for request in &requests {
+        let mut stream = std::net::TcpStream::connect(server_address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"),
+            "{}",
+            response
+        );
+    }
+    handle.join().unwrap();
+}
+
+/// Echoes back the `X-Echo`, `Authorization` and `User-Agent` request headers.
+#[cfg(test)]
+pub struct EchoHeaderHandler;
+
+#[cfg(test)]
+impl<I: io::Read> HttpRequestHandler<I> for EchoHeaderHandler {
+    type Error = HttpResponse<Box<dyn io::Read>>;
+
+    fn get<'a>(
+        &'a mut self,
+        _: String,
+        headers: &HttpHeaders,
+    ) -> Result<HttpResponse<Box<dyn io::Read + 'a>>, Self::Error> {
+        let mut res = HttpResponse::new(HttpStatus::OK, Box::new(io::empty()) as Box<dyn io::Read>);
+        for key in ["X-Echo", "Authorization", "User-Agent"] {
+            if let Some(value) = headers.get(key) {
+                res.add_header(key, value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[test]
+fn server_handler_can_read_request_headers() {
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let server_address = server_socket.local_addr().unwrap();
+    let mut server = HttpServer::new(server_socket, EchoHeaderHandler);
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let url = format!("http://localhost:{}/", server_address.port());
+    let stream = std::net::TcpStream::connect(server_address).unwrap();
+    let response = crate::client::HttpRequestBuilder::get(&url[..])
+        .unwrap()
+        .add_header("X-Echo", "hello")
+        .send(stream)
+        .unwrap()
+        .finish()
+        .unwrap();
+    handle.join().unwrap().unwrap();
+
+    assert_eq!(response.get_header("X-Echo"), Some("hello"));
+}
+
+#[test]
+fn fn_handler_responds_to_get() {
+    let handler = fn_handler(|method, uri| match method {
+        HttpMethod::Get => Ok(HttpResponse::from_string(
+            HttpStatus::OK,
+            format!("you asked for {}", uri),
+        )),
+        _ => Ok(HttpResponse::from_string(HttpStatus::MethodNotAllowed, "")),
+    });
+    let server_socket = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = server_socket.local_addr().unwrap().port();
+    let mut server = HttpServer::new(server_socket, handler);
+    let handle = std::thread::spawn(move || {
+        for _ in 0..2 {
+            server.serve_one().unwrap();
+        }
+    });
+
+    let url = format!("http://localhost:{}/thing", port);
+    let response = crate::client::request(HttpMethod::Get, &url[..], io::empty()).unwrap();
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.into_string().unwrap(), "you asked for /thing");
+
+    let response = crate::client::request(HttpMethod::Put, &url[..], &b"data"[..]).unwrap();
+    assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+
+    handle.join().unwrap();
+}
@@ -0,0 +1,179 @@
+//! Parsing `text/event-stream` bodies, the server-sent events of the HTML standard.
+//!
+//! # Example
+//! ```rust
+//! use http_io::protocol::HttpResponse;
+//! use http_io::sse::EventStream;
+//!
+//! # fn main() -> http_io::error::Result<()> {
+//! let input = "HTTP/1.1 200 OK\r\n\
+//!              Content-Type: text/event-stream\r\n\
+//!              \r\n\
+//!              data: hello\n\n";
+//! let response = HttpResponse::deserialize(input.as_bytes())?;
+//! for event in EventStream::new(response.body) {
+//!     println!("{}", event?.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::error::Result;
+use crate::io;
+use crate::protocol::{HttpBody, Lines};
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+/// One event of an event stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerSentEvent {
+    /// The type of the event, `message` unless the stream gave another with an `event` field.
+    pub event: String,
+    /// The `data` fields of the event, joined with newlines.
+    pub data: String,
+    /// The last `id` the stream gave, which may have been for an earlier event. A client
+    /// reconnecting sends it in the `Last-Event-ID` header.
+    pub id: Option<String>,
+    /// How many milliseconds to wait before reconnecting, if the event came with a `retry` field.
+    pub retry: Option<u64>,
+}
+
+/// An iterator over the events of a `text/event-stream` body.
+///
+/// Lines ending with a lone `\r` aren't recognized, only ones ending with `\n` or `\r\n`. An event
+/// cut off by the end of the stream is dropped, as it may not be complete.
+pub struct EventStream<S: io::Read> {
+    lines: Lines<S>,
+    first_line: bool,
+    last_id: Option<String>,
+}
+
+impl<S: io::Read> EventStream<S> {
+    pub fn new(body: HttpBody<S>) -> Self {
+        EventStream {
+            lines: body.lines(),
+            first_line: true,
+            last_id: None,
+        }
+    }
+}
+
+impl<S: io::Read> Iterator for EventStream<S> {
+    type Item = Result<ServerSentEvent>;
+
+    fn next(&mut self) -> Option<Result<ServerSentEvent>> {
+        let mut event = None;
+        let mut data: Option<String> = None;
+        let mut retry = None;
+        loop {
+            let mut line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if core::mem::take(&mut self.first_line) && line.starts_with('\u{feff}') {
+                line.remove(0);
+            }
+
+            // A blank line dispatches the event, unless it has no data.
+            if line.is_empty() {
+                match data.take() {
+                    Some(data) => {
+                        return Some(Ok(ServerSentEvent {
+                            event: event.unwrap_or_else(|| "message".into()),
+                            data,
+                            id: self.last_id.clone(),
+                            retry,
+                        }))
+                    }
+                    None => {
+                        event = None;
+                        continue;
+                    }
+                }
+            }
+
+            // Lines starting with a colon are comments, often sent to keep the connection open.
+            let (field, value) = match line.find(':') {
+                Some(0) => continue,
+                Some(i) => {
+                    let value = &line[i + 1..];
+                    (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+                }
+                None => (&line[..], ""),
+            };
+            match field {
+                "event" => event = Some(value.into()),
+                "data" => match &mut data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.into()),
+                },
+                "id" if !value.contains('\0') => self.last_id = Some(value.into()),
+                "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    retry = value.parse().ok()
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod sse_tests {
+    use super::{EventStream, ServerSentEvent};
+    use crate::protocol::HttpBody;
+    use std::io;
+
+    fn events(input: &'static str) -> Vec<ServerSentEvent> {
+        let body = HttpBody::new(None, None, io::BufReader::new(input.as_bytes()));
+        EventStream::new(body).map(Result::unwrap).collect()
+    }
+
+    fn event(event: &str, data: &str, id: Option<&str>) -> ServerSentEvent {
+        ServerSentEvent {
+            event: event.into(),
+            data: data.into(),
+            id: id.map(Into::into),
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn canonical_stream() {
+        let input = "\u{feff}: this is a comment\n\
+                     data: first\n\
+                     data:  second\n\
+                     \n\
+                     id: 1\n\
+                     event: update\r\n\
+                     data\n\
+                     data:{\"a\": 1}\n\
+                     \n\
+                     : keep alive\n\
+                     \n\
+                     event: ignored without data\n\
+                     \n\
+                     data: third\n\
+                     \n\
+                     data: cut off\n";
+        assert_eq!(
+            events(input),
+            [
+                event("message", "first\n second", None),
+                event("update", "\n{\"a\": 1}", Some("1")),
+                event("message", "third", Some("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_and_id() {
+        let input = "retry: 1000\ndata: a\n\nretry: soon\nid: 2\0\ndata: b\n\nid\ndata: c\n\n";
+        let events = events(input);
+        assert_eq!(events[0].retry, Some(1000));
+        assert_eq!(events[1].retry, None);
+        assert_eq!(events[1].id, None);
+        assert_eq!(events[2].id, Some("".into()));
+    }
+}
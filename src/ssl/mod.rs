@@ -1,11 +1,118 @@
-// disable false positive of not using the String, the dead code analysis
-// intentionally ignore we derive the Debug trait
-#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Error(String);
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A version of the TLS protocol. Not every backend supports every version, rustls only supports
+/// TLS 1.2 and 1.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+/// A certificate chain and private key, for connecting to servers which require clients to
+/// authenticate themselves.
+#[derive(Debug, Clone)]
+pub struct SslClientCertificate {
+    /// PEM encoded certificates, starting with the client's own.
+    pub cert_chain_pem: Vec<u8>,
+    /// PEM encoded PKCS #8 private key of the first certificate in the chain.
+    pub private_key_pem: Vec<u8>,
+}
+
+/// A certificate presented by the other end of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SslPeerCertificate {
+    /// The DER encoded X.509 certificate.
+    pub der: Vec<u8>,
+}
+
+/// Options for the TLS connections made by a client.
+#[derive(Debug, Clone, Default)]
+pub struct SslClientConfig {
+    /// The oldest version of TLS to accept, or the backend's default if `None`.
+    pub min_version: Option<TlsVersion>,
+    /// The newest version of TLS to offer, or the backend's default if `None`.
+    pub max_version: Option<TlsVersion>,
+    /// The protocols to offer with ALPN, most preferred first. For instance `"http/1.1"`.
+    pub alpn_protocols: Vec<String>,
+    /// PEM encoded certificates to trust as roots, in addition to the default ones. Useful for
+    /// services using a private certificate authority.
+    pub root_certificates: Vec<Vec<u8>>,
+    /// The certificate to present if the server asks for one.
+    pub client_certificate: Option<SslClientCertificate>,
+    /// If not empty, only accept servers whose certificate has one of these public key pins. A pin
+    /// is the SHA-256 hash of the DER encoded SubjectPublicKeyInfo of the certificate. The
+    /// certificate must still be valid, unless invalid certificates are accepted.
+    pub pinned_public_keys: Vec<[u8; 32]>,
+    accept_invalid_certs: bool,
+}
+
+impl SslClientConfig {
+    /// Accept any certificate the server presents, whether it is expired, self-signed, or for
+    /// another host. This makes the connection vulnerable to man-in-the-middle attacks, it should
+    /// only be used for local testing.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Fail if any ALPN protocol name can't be sent, RFC 7301 section 3.1 limits them to between 1
+    /// and 255 bytes.
+    fn check_alpn_protocols(&self) -> Result<()> {
+        match self
+            .alpn_protocols
+            .iter()
+            .find(|p| p.is_empty() || p.len() > 255)
+        {
+            Some(p) => Err(Error(format!(
+                "ALPN protocol name must be between 1 and 255 bytes long, got {} bytes",
+                p.len()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail unless the server's certificate, the first one it presented, has one of the pinned
+    /// public keys. Does nothing if no keys are pinned.
+    fn check_pins(&self, peer_certificates: Option<Vec<SslPeerCertificate>>) -> Result<()> {
+        if self.pinned_public_keys.is_empty() {
+            return Ok(());
+        }
+        let pin = peer_certificates
+            .as_ref()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| inner::public_key_pin(&cert.der));
+        match pin {
+            Some(pin) if self.pinned_public_keys.contains(&pin) => Ok(()),
+            _ => Err(Error(
+                "the server's certificate doesn't match any pinned public key".into(),
+            )),
+        }
+    }
+
+    /// Whether the given version is within the configured range.
+    #[allow(dead_code)]
+    fn allows(&self, version: TlsVersion) -> bool {
+        self.min_version.is_none_or(|min| version >= min)
+            && self.max_version.is_none_or(|max| version <= max)
+    }
+}
+
+#[cfg(not(feature = "openssl"))]
+mod pin;
+
 #[cfg(feature = "openssl")]
 #[path = "openssl.rs"]
 mod inner;
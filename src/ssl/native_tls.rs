@@ -1,5 +1,6 @@
-use super::{Error, Result};
+use super::{Error, Result, SslClientConfig, SslPeerCertificate, TlsVersion};
 use crate::server::Listen;
+use std::net::SocketAddr;
 use std::{fmt, io};
 
 #[cfg(test)]
@@ -16,10 +17,39 @@ fn read_test_cert(name: &str) -> Result<Vec<u8>> {
 
 pub struct SslClientStream<Stream>(native_tls::TlsStream<Stream>);
 
+fn protocol(version: TlsVersion) -> native_tls::Protocol {
+    match version {
+        TlsVersion::Tls1_0 => native_tls::Protocol::Tlsv10,
+        TlsVersion::Tls1_1 => native_tls::Protocol::Tlsv11,
+        TlsVersion::Tls1_2 => native_tls::Protocol::Tlsv12,
+        TlsVersion::Tls1_3 => native_tls::Protocol::Tlsv13,
+    }
+}
+
 impl<Stream: io::Read + io::Write + fmt::Debug + 'static> SslClientStream<Stream> {
     pub fn new(host: &str, stream: Stream) -> Result<Self> {
-        #[allow(unused_mut)]
+        Self::with_config(host, stream, &SslClientConfig::default())
+    }
+
+    /// Connect using the given TLS versions, ALPN protocols and certificate verification options.
+    pub fn with_config(host: &str, stream: Stream, ssl_config: &SslClientConfig) -> Result<Self> {
+        ssl_config.check_alpn_protocols()?;
         let mut builder = native_tls::TlsConnector::builder();
+        builder.min_protocol_version(ssl_config.min_version.map(protocol));
+        builder.max_protocol_version(ssl_config.max_version.map(protocol));
+        let alpn: Vec<&str> = ssl_config.alpn_protocols.iter().map(|p| &p[..]).collect();
+        builder.request_alpns(&alpn);
+        for pem in &ssl_config.root_certificates {
+            builder.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        }
+        if let Some(c) = &ssl_config.client_certificate {
+            builder.identity(native_tls::Identity::from_pkcs8(
+                &c.cert_chain_pem,
+                &c.private_key_pem,
+            )?);
+        }
+        builder.danger_accept_invalid_certs(ssl_config.accept_invalid_certs);
+        builder.danger_accept_invalid_hostnames(ssl_config.accept_invalid_certs);
 
         #[cfg(test)]
         builder.add_root_certificate(native_tls::Certificate::from_pem(&read_test_cert(
@@ -27,8 +57,31 @@ impl<Stream: io::Read + io::Write + fmt::Debug + 'static> SslClientStream<Stream
         )?)?);
 
         let connector = builder.build()?;
-        Ok(Self(connector.connect(host, stream)?))
+        let stream = Self(connector.connect(host, stream)?);
+        ssl_config.check_pins(stream.peer_certificates())?;
+        Ok(stream)
     }
+
+    /// The underlying stream. Reading from or writing to it directly will corrupt the TLS session.
+    pub fn get_ref(&self) -> &Stream {
+        self.0.get_ref()
+    }
+
+    /// The certificates the server presented. native-tls only gives access to the server's own
+    /// certificate, not the rest of its chain.
+    pub fn peer_certificates(&self) -> Option<Vec<SslPeerCertificate>> {
+        let cert = self.0.peer_certificate().ok()??;
+        Some(vec![SslPeerCertificate {
+            der: cert.to_der().ok()?,
+        }])
+    }
+}
+
+/// The pin of a DER encoded X.509 certificate, the SHA-256 hash of its SubjectPublicKeyInfo.
+/// Returns `None` if the certificate can't be parsed. native-tls can't look inside certificates
+/// or hash anything, so this falls back on `pin`.
+pub(super) fn public_key_pin(cert: &[u8]) -> Option<[u8; 32]> {
+    super::pin::public_key_pin(cert)
 }
 
 impl<Stream: io::Read + io::Write> io::Read for SslClientStream<Stream> {
@@ -100,10 +153,11 @@ where
 {
     type Stream = SslServerStream<<L as Listen>::Stream>;
 
-    fn accept(&self) -> crate::error::Result<Self::Stream> {
-        let stream = self.listener.accept()?;
-        Ok(SslServerStream(
-            self.acceptor.accept(stream).map_err(|e| Error::from(e))?,
+    fn accept(&self) -> crate::error::Result<(Self::Stream, Option<SocketAddr>)> {
+        let (stream, addr) = self.listener.accept()?;
+        Ok((
+            SslServerStream(self.acceptor.accept(stream).map_err(Error::from)?),
+            addr,
         ))
     }
 }
@@ -1,15 +1,47 @@
-use super::{Error, Result};
+use super::{Error, Result, SslClientConfig, SslPeerCertificate, TlsVersion};
 use crate::server::Listen;
+use std::net::SocketAddr;
 use std::{fmt, io};
 
 pub struct SslClientStream<Stream>(openssl::ssl::SslStream<Stream>);
 
+fn ssl_version(version: TlsVersion) -> openssl::ssl::SslVersion {
+    use openssl::ssl::SslVersion;
+
+    match version {
+        TlsVersion::Tls1_0 => SslVersion::TLS1,
+        TlsVersion::Tls1_1 => SslVersion::TLS1_1,
+        TlsVersion::Tls1_2 => SslVersion::TLS1_2,
+        TlsVersion::Tls1_3 => SslVersion::TLS1_3,
+    }
+}
+
 impl<Stream: io::Read + io::Write + fmt::Debug> SslClientStream<Stream> {
     pub fn new(host: &str, stream: Stream) -> Result<Self> {
+        Self::with_config(host, stream, &SslClientConfig::default())
+    }
+
+    /// Connect using the given TLS versions, ALPN protocols and certificate verification options.
+    pub fn with_config(host: &str, stream: Stream, ssl_config: &SslClientConfig) -> Result<Self> {
+        use openssl::pkey::PKey;
         use openssl::ssl::{Ssl, SslContext, SslMethod, SslVerifyMode};
+        use openssl::x509::X509;
 
+        ssl_config.check_alpn_protocols()?;
         let mut ctx = SslContext::builder(SslMethod::tls())?;
         ctx.set_default_verify_paths()?;
+        ctx.set_min_proto_version(ssl_config.min_version.map(ssl_version))?;
+        ctx.set_max_proto_version(ssl_config.max_version.map(ssl_version))?;
+
+        if !ssl_config.alpn_protocols.is_empty() {
+            // ALPN protocols are sent length-prefixed, see RFC 7301 section 3.1.
+            let mut protos = Vec::new();
+            for p in &ssl_config.alpn_protocols {
+                protos.push(p.len() as u8);
+                protos.extend_from_slice(p.as_bytes());
+            }
+            ctx.set_alpn_protos(&protos)?;
+        }
 
         #[cfg(test)]
         {
@@ -17,13 +49,65 @@ impl<Stream: io::Read + io::Write + fmt::Debug> SslClientStream<Stream> {
             ctx.set_ca_file(manifest_dir.join("test_ca.pem"))?;
         }
 
-        ctx.set_verify(SslVerifyMode::PEER);
+        for pem in &ssl_config.root_certificates {
+            for cert in X509::stack_from_pem(pem)? {
+                ctx.cert_store_mut().add_cert(cert)?;
+            }
+        }
+
+        if let Some(c) = &ssl_config.client_certificate {
+            let mut chain = X509::stack_from_pem(&c.cert_chain_pem)?.into_iter();
+            let cert = chain
+                .next()
+                .ok_or_else(|| Error("client certificate chain is empty".into()))?;
+            ctx.set_certificate(&cert)?;
+            for cert in chain {
+                ctx.add_extra_chain_cert(cert)?;
+            }
+            ctx.set_private_key(PKey::private_key_from_pem(&c.private_key_pem)?.as_ref())?;
+            ctx.check_private_key()?;
+        }
+
+        if ssl_config.accept_invalid_certs {
+            ctx.set_verify(SslVerifyMode::NONE);
+        } else {
+            ctx.set_verify(SslVerifyMode::PEER);
+        }
 
         let mut ssl = Ssl::new(&ctx.build())?;
         ssl.param_mut().set_host(host)?;
         ssl.set_hostname(host)?;
-        Ok(Self(ssl.connect(stream)?))
+        let stream = Self(ssl.connect(stream)?);
+        ssl_config.check_pins(stream.peer_certificates())?;
+        Ok(stream)
+    }
+
+    /// The underlying stream. Reading from or writing to it directly will corrupt the TLS session.
+    pub fn get_ref(&self) -> &Stream {
+        self.0.get_ref()
     }
+
+    /// The certificates the server presented, starting with its own.
+    pub fn peer_certificates(&self) -> Option<Vec<SslPeerCertificate>> {
+        self.0
+            .ssl()
+            .peer_cert_chain()?
+            .iter()
+            .map(|cert| {
+                Some(SslPeerCertificate {
+                    der: cert.to_der().ok()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The pin of a DER encoded X.509 certificate, the SHA-256 hash of its SubjectPublicKeyInfo.
+/// Returns `None` if the certificate can't be parsed.
+pub(super) fn public_key_pin(cert: &[u8]) -> Option<[u8; 32]> {
+    let cert = openssl::x509::X509::from_der(cert).ok()?;
+    let public_key = cert.public_key().ok()?.public_key_to_der().ok()?;
+    Some(openssl::sha::sha256(&public_key))
 }
 
 impl<Stream: io::Read + io::Write> io::Read for SslClientStream<Stream> {
@@ -83,15 +167,29 @@ pub struct SslListener<L> {
 
 impl<L: Listen> SslListener<L> {
     pub fn new(private_key_pem: &[u8], cert_pem: &[u8], listener: L) -> Result<Self> {
-        use openssl::pkey::PKey;
-        use openssl::ssl::{SslAcceptor, SslMethod};
-        use openssl::x509::X509;
+        let acceptor = acceptor_builder(private_key_pem, cert_pem)?;
+        Ok(Self {
+            listener,
+            acceptor: acceptor.build(),
+        })
+    }
 
-        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
-        acceptor.set_private_key(PKey::private_key_from_pem(private_key_pem)?.as_ref())?;
-        acceptor.set_certificate(X509::from_pem(cert_pem)?.as_ref())?;
+    /// Like `new`, but require clients to present a certificate signed by one of the given PEM
+    /// encoded certificate authorities.
+    pub fn with_client_auth(
+        private_key_pem: &[u8],
+        cert_pem: &[u8],
+        client_ca_pem: &[u8],
+        listener: L,
+    ) -> Result<Self> {
+        use openssl::ssl::SslVerifyMode;
+        use openssl::x509::X509;
 
-        acceptor.check_private_key()?;
+        let mut acceptor = acceptor_builder(private_key_pem, cert_pem)?;
+        for cert in X509::stack_from_pem(client_ca_pem)? {
+            acceptor.cert_store_mut().add_cert(cert)?;
+        }
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
 
         Ok(Self {
             listener,
@@ -100,16 +198,33 @@ impl<L: Listen> SslListener<L> {
     }
 }
 
+fn acceptor_builder(
+    private_key_pem: &[u8],
+    cert_pem: &[u8],
+) -> Result<openssl::ssl::SslAcceptorBuilder> {
+    use openssl::pkey::PKey;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use openssl::x509::X509;
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    acceptor.set_private_key(PKey::private_key_from_pem(private_key_pem)?.as_ref())?;
+    acceptor.set_certificate(X509::from_pem(cert_pem)?.as_ref())?;
+
+    acceptor.check_private_key()?;
+    Ok(acceptor)
+}
+
 impl<L: Listen> Listen for SslListener<L>
 where
     <L as Listen>::Stream: fmt::Debug,
 {
     type Stream = SslServerStream<<L as Listen>::Stream>;
 
-    fn accept(&self) -> crate::error::Result<Self::Stream> {
-        let stream = self.listener.accept()?;
-        Ok(SslServerStream(
-            self.acceptor.accept(stream).map_err(|e| Error::from(e))?,
+    fn accept(&self) -> crate::error::Result<(Self::Stream, Option<SocketAddr>)> {
+        let (stream, addr) = self.listener.accept()?;
+        Ok((
+            SslServerStream(self.acceptor.accept(stream).map_err(Error::from)?),
+            addr,
         ))
     }
 }
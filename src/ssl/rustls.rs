@@ -1,7 +1,8 @@
-use super::{Error, Result};
+use super::{Error, Result, SslClientConfig, SslPeerCertificate, TlsVersion};
 use crate::io;
 use crate::server::Listen;
 use std::convert::TryInto as _;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 #[cfg(test)]
@@ -16,7 +17,28 @@ fn read_test_cert(name: &str) -> Result<Vec<u8>> {
     Ok(bytes)
 }
 
-fn root_store() -> Result<rustls::RootCertStore> {
+fn parse_certs(pem: &[u8]) -> Result<Vec<rustls::Certificate>> {
+    let mut certs = Vec::new();
+    for cert in rustls_pemfile::certs(&mut io::BufReader::new(pem)) {
+        certs.push(rustls::Certificate(cert?.to_vec()));
+    }
+    Ok(certs)
+}
+
+fn parse_private_key(pem: &[u8]) -> Result<rustls::PrivateKey> {
+    let key = rustls_pemfile::private_key(&mut io::BufReader::new(pem))?
+        .ok_or_else(|| Error("no private key found".into()))?;
+    Ok(rustls::PrivateKey(key.secret_der().to_vec()))
+}
+
+fn add_pem_certs(root_store: &mut rustls::RootCertStore, pem: &[u8]) -> Result<()> {
+    for c in parse_certs(pem)? {
+        root_store.add(&c).map_err(|e| Error(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn root_store(extra_roots: &[Vec<u8>]) -> Result<rustls::RootCertStore> {
     let mut root_store = rustls::RootCertStore::empty();
     root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
         rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
@@ -27,25 +49,78 @@ fn root_store() -> Result<rustls::RootCertStore> {
     }));
 
     #[cfg(test)]
-    for c in rustls_pemfile::certs(&mut io::BufReader::new(&read_test_cert("test_ca.pem")?[..])) {
-        root_store
-            .add(&rustls::Certificate(c?.to_vec()))
-            .map_err(|e| Error(e.to_string()))?;
+    add_pem_certs(&mut root_store, &read_test_cert("test_ca.pem")?)?;
+
+    for pem in extra_roots {
+        add_pem_certs(&mut root_store, pem)?;
     }
 
     Ok(root_store)
 }
 
+/// Accepts any certificate, for `SslClientConfig::danger_accept_invalid_certs`.
+struct NoCertificateVerification;
+
+impl rustls::client::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
 pub struct SslClientStream<Stream: io::Read + io::Write>(
     rustls::StreamOwned<rustls::ClientConnection, Stream>,
 );
 
 impl<Stream: io::Read + io::Write> SslClientStream<Stream> {
-    pub fn new(host: &str, mut stream: Stream) -> Result<Self> {
-        let config = rustls::ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_store()?)
-            .with_no_client_auth();
+    pub fn new(host: &str, stream: Stream) -> Result<Self> {
+        Self::with_config(host, stream, &SslClientConfig::default())
+    }
+
+    /// Connect using the given TLS versions, ALPN protocols and certificate verification options.
+    pub fn with_config(
+        host: &str,
+        mut stream: Stream,
+        ssl_config: &SslClientConfig,
+    ) -> Result<Self> {
+        ssl_config.check_alpn_protocols()?;
+        let versions: Vec<_> = [
+            (TlsVersion::Tls1_2, &rustls::version::TLS12),
+            (TlsVersion::Tls1_3, &rustls::version::TLS13),
+        ]
+        .iter()
+        .filter(|(v, _)| ssl_config.allows(*v))
+        .map(|(_, p)| *p)
+        .collect();
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&versions)?
+            .with_root_certificates(root_store(&ssl_config.root_certificates)?);
+        let mut config = match &ssl_config.client_certificate {
+            Some(c) => builder.with_single_cert(
+                parse_certs(&c.cert_chain_pem)?,
+                parse_private_key(&c.private_key_pem)?,
+            )?,
+            None => builder.with_no_client_auth(),
+        };
+        if ssl_config.accept_invalid_certs {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoCertificateVerification));
+        }
+        config.alpn_protocols = ssl_config
+            .alpn_protocols
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect();
         assert!(config.enable_sni);
 
         let server_name = host.try_into()?;
@@ -68,10 +143,39 @@ impl<Stream: io::Read + io::Write> SslClientStream<Stream> {
             return Err(Error("SSL handshake failed".into()));
         }
 
-        Ok(Self(rustls::StreamOwned::new(conn, stream)))
+        let stream = Self(rustls::StreamOwned::new(conn, stream));
+        ssl_config.check_pins(stream.peer_certificates())?;
+        Ok(stream)
+    }
+
+    /// The underlying stream. Reading from or writing to it directly will corrupt the TLS session.
+    pub fn get_ref(&self) -> &Stream {
+        &self.0.sock
+    }
+
+    /// The certificates the server presented, starting with its own.
+    pub fn peer_certificates(&self) -> Option<Vec<SslPeerCertificate>> {
+        let certs = self.0.conn.peer_certificates()?;
+        Some(
+            certs
+                .iter()
+                .map(|cert| SslPeerCertificate {
+                    der: cert.0.clone(),
+                })
+                .collect(),
+        )
     }
 }
 
+/// The pin of a DER encoded X.509 certificate, the SHA-256 hash of its SubjectPublicKeyInfo.
+/// Returns `None` if the certificate can't be parsed. Neither rustls nor webpki give access to the
+/// SubjectPublicKeyInfo, so it is found with `pin::subject_public_key_info`.
+pub(super) fn public_key_pin(cert: &[u8]) -> Option<[u8; 32]> {
+    let public_key = super::pin::subject_public_key_info(cert)?;
+    let hash = ring::digest::digest(&ring::digest::SHA256, public_key);
+    hash.as_ref().try_into().ok()
+}
+
 impl<Stream: io::Read + io::Write> io::Read for SslClientStream<Stream> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.0.read(buf)
@@ -131,21 +235,33 @@ pub struct SslListener<L> {
 
 impl<L: Listen> SslListener<L> {
     pub fn new(private_key_pem: &[u8], cert_pem: &[u8], listener: L) -> Result<Self> {
-        let private_key = rustls::PrivateKey(
-            rustls_pemfile::private_key(&mut io::BufReader::new(private_key_pem))?
-                .unwrap()
-                .secret_der()
-                .to_vec(),
-        );
-        let mut certs = Vec::new();
-        for cert in rustls_pemfile::certs(&mut io::BufReader::new(cert_pem)) {
-            certs.push(rustls::Certificate(cert?.to_vec()));
-        }
-
         let config = rustls::ServerConfig::builder()
             .with_safe_defaults()
             .with_no_client_auth()
-            .with_single_cert(certs, private_key)?;
+            .with_single_cert(parse_certs(cert_pem)?, parse_private_key(private_key_pem)?)?;
+
+        Ok(Self {
+            listener,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Like `new`, but require clients to present a certificate signed by one of the given PEM
+    /// encoded certificate authorities.
+    pub fn with_client_auth(
+        private_key_pem: &[u8],
+        cert_pem: &[u8],
+        client_ca_pem: &[u8],
+        listener: L,
+    ) -> Result<Self> {
+        let mut client_roots = rustls::RootCertStore::empty();
+        add_pem_certs(&mut client_roots, client_ca_pem)?;
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(
+                client_roots,
+            ))
+            .with_single_cert(parse_certs(cert_pem)?, parse_private_key(private_key_pem)?)?;
 
         Ok(Self {
             listener,
@@ -188,12 +304,13 @@ impl<L: Listen> SslListener<L> {
 impl<L: Listen> Listen for SslListener<L> {
     type Stream = SslServerStream<<L as Listen>::Stream>;
 
-    fn accept(&self) -> crate::error::Result<Self::Stream> {
-        let mut stream = self.listener.accept()?;
-        let conn = self
-            .get_conn_from_stream(&mut stream)
-            .map_err(|e| Error::from(e))?;
-        Ok(SslServerStream(rustls::StreamOwned::new(conn, stream)))
+    fn accept(&self) -> crate::error::Result<(Self::Stream, Option<SocketAddr>)> {
+        let (mut stream, addr) = self.listener.accept()?;
+        let conn = self.get_conn_from_stream(&mut stream)?;
+        Ok((
+            SslServerStream(rustls::StreamOwned::new(conn, stream)),
+            addr,
+        ))
     }
 }
 
@@ -3,6 +3,8 @@ use crate::error::{Error, Result};
 use alloc::format;
 #[cfg(not(feature = "std"))]
 use alloc::string::{String, ToString};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::fmt;
 use core::str;
@@ -58,9 +60,61 @@ impl HttpUrl {
     pub fn host(&self) -> &str {
         &self.host
     }
+
+    /// The host to connect to. It is the same as `host`, except that IPv6 addresses don't have the
+    /// brackets they are written with in urls and `Host` headers.
+    pub fn socket_host(&self) -> &str {
+        self.host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(&self.host)
+    }
+
+    /// The host and port as sent in the `Host` header. The port is left out when it is the default
+    /// one for the scheme.
+    pub fn authority(&self) -> String {
+        match self.url.port() {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        }
+    }
     pub fn url(&self) -> &Url {
         &self.url
     }
+
+    /// Resolve a reference which may be relative, like the `Location` of a redirect or the `href`
+    /// of a link, against this URL. The result must still be an `http` or `https` URL.
+    pub fn join(&self, relative: &str) -> Result<HttpUrl> {
+        let url = self
+            .url
+            .join(relative)
+            .map_err(|err| Error::UrlError(err.to_string()))?;
+        HttpUrl::try_from(url)
+    }
+
+    /// Replace the path of the URL, percent-encoding characters like spaces which can't appear in
+    /// a path as they are. The scheme, host and port are kept, but the query and fragment are too,
+    /// so clear those separately if needed. The path must be absolute, use `join` to resolve a
+    /// relative one.
+    pub fn set_path(&mut self, path: &str) -> Result<()> {
+        if !path.is_empty() && !path.starts_with('/') {
+            return Err(Error::UrlError(format!("path '{}' is not absolute", path)));
+        }
+        self.url.set_path(path);
+        Ok(())
+    }
+
+    /// Like `set_path`, but consumes the URL and returns the changed one.
+    pub fn with_path(mut self, path: &str) -> Result<Self> {
+        self.set_path(path)?;
+        Ok(self)
+    }
+}
+
+impl From<HttpUrl> for Url {
+    fn from(url: HttpUrl) -> Self {
+        url.url
+    }
 }
 
 #[inline]
@@ -111,6 +165,51 @@ impl fmt::Display for HttpUrl {
     }
 }
 
+/// Percent-encode a path segment or query component. Everything except the unreserved characters
+/// from RFC 3986 section 2.3 is encoded.
+pub fn encode_component(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Decode the `%XX` sequences in a path segment or query component.
+pub fn decode_component(s: &str) -> Result<String> {
+    let mut bytes = s.as_bytes().iter();
+    let mut decoded = Vec::with_capacity(s.len());
+    while let Some(&b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let escape = bytes.as_slice().get(..2).and_then(|hex| {
+            let hex = str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| !hex.starts_with('+'))
+        });
+        match escape {
+            Some(d) => {
+                decoded.push(d);
+                bytes.nth(1);
+            }
+            None => {
+                return Err(Error::UrlError(format!(
+                    "malformed percent-encoding in {:?}",
+                    s
+                )))
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|e| Error::Utf8Error(e.utf8_error()))
+}
+
 #[cfg(test)]
 mod tests {
     extern crate std;
@@ -336,6 +435,92 @@ mod tests {
             error_unsupported_url_scheme("wss"),
         );
     }
+    #[test]
+    fn encode_component_reserved_and_unicode() {
+        assert_eq!(encode_component("azAZ09-._~"), "azAZ09-._~");
+        assert_eq!(encode_component("a b&c=d/e?"), "a%20b%26c%3Dd%2Fe%3F");
+        assert_eq!(encode_component("été"), "%C3%A9t%C3%A9");
+        assert_eq!(encode_component("100%"), "100%25");
+    }
+
+    #[test]
+    fn decode_component_round_trip() {
+        for s in ["", "plain", "a b&c=d/e?", "été", "100%", "+"] {
+            assert_eq!(decode_component(&encode_component(s)).unwrap(), s);
+        }
+        assert_eq!(decode_component("%c3%a9").unwrap(), "é");
+        assert_eq!(decode_component("a+b").unwrap(), "a+b");
+    }
+
+    #[test]
+    fn decode_component_malformed() {
+        for s in ["%2", "%GG", "abc%", "%+1", "%%41"] {
+            assert!(
+                matches!(decode_component(s), Err(Error::UrlError(_))),
+                "{}",
+                s
+            );
+        }
+        assert!(matches!(decode_component("%FF"), Err(Error::Utf8Error(_))));
+    }
+
+    #[test]
+    fn ipv6_host() {
+        let url = HttpUrl::from_str("http://[::1]:8080/").unwrap();
+        assert_eq!(url.host(), "[::1]");
+        assert_eq!(url.socket_host(), "::1");
+        assert_eq!(url.port(), 8080);
+
+        let url = HttpUrl::from_str("https://example.com/").unwrap();
+        assert_eq!(url.socket_host(), "example.com");
+    }
+
+    #[test]
+    fn http_url_set_path() {
+        let mut url = HttpUrl::from_str("https://example.com:8443/a?x=1").unwrap();
+        url.set_path("/b c/d").unwrap();
+        assert_eq!(url.to_string(), "https://example.com:8443/b%20c/d?x=1");
+        assert_eq!(url.scheme(), Scheme::Https);
+        assert_eq!(url.host(), "example.com");
+        assert_eq!(url.port(), 8443);
+
+        let url = url.with_path("/caf\u{e9}#1").unwrap();
+        assert_eq!(url.url().path(), "/caf%C3%A9%231");
+
+        let url = url.with_path("").unwrap();
+        assert_eq!(url.url().path(), "/");
+
+        assert!(matches!(url.with_path("relative"), Err(Error::UrlError(_))));
+    }
+
+    #[test]
+    fn http_url_join() {
+        let base = HttpUrl::from_str("https://example.com/a/b/c?x=1#top").unwrap();
+        let join = |relative| base.join(relative).unwrap().to_string();
+        assert_eq!(join("d"), "https://example.com/a/b/d");
+        assert_eq!(join("../x"), "https://example.com/a/x");
+        assert_eq!(join("/abs"), "https://example.com/abs");
+        assert_eq!(join("?q=2"), "https://example.com/a/b/c?q=2");
+        assert_eq!(join("#frag"), "https://example.com/a/b/c?x=1#frag");
+        assert_eq!(join("//other.com/x"), "https://other.com/x");
+        assert_eq!(join("http://plain.com/"), "http://plain.com/");
+
+        let joined = base.join("//other.com:8443/x").unwrap();
+        assert_eq!(joined.host(), "other.com");
+        assert_eq!(joined.port(), 8443);
+        assert_eq!(joined.scheme(), Scheme::Https);
+    }
+
+    #[test]
+    fn http_url_join_errors() {
+        let base = HttpUrl::from_str("http://example.com/a").unwrap();
+        assert!(matches!(
+            base.join("ftp://example.com/"),
+            Err(Error::UrlError(_))
+        ));
+        assert!(matches!(base.join("http://[::1/"), Err(Error::UrlError(_))));
+    }
+
     #[test]
     fn check_url_must_have_host() {
         let mut url = Url::parse("http://a.com/b/c/d").unwrap();
@@ -0,0 +1,214 @@
+//! Tests for the io module the crate provides in place of `std::io` for `no_std` builds, run
+//! them with `cargo test --no-default-features --test no_std_io`.
+#![cfg(not(feature = "std"))]
+
+use http_io::io::{
+    self, BufRead as _, BufReader, BufWriter, Cursor, Read, Result, Seek as _, SeekFrom, Write,
+};
+
+/// Records each write it is given, accepting at most `max_write` bytes of each.
+struct CountingWriter {
+    writes: Vec<Vec<u8>>,
+    max_write: usize,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(self.max_write);
+        self.writes.push(buf[..len].to_vec());
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn buf_writer_coalesces_writes() {
+    let inner = CountingWriter {
+        writes: Vec::new(),
+        max_write: usize::MAX,
+    };
+    let mut w = BufWriter::new(inner);
+    for i in 0..100 {
+        write!(&mut w, "Header-{}: value\r\n", i).unwrap();
+    }
+    assert!(w.get_ref().writes.is_empty());
+    w.flush().unwrap();
+
+    let inner = w.into_inner().unwrap();
+    assert_eq!(inner.writes.len(), 1);
+    assert!(inner.writes[0].starts_with(b"Header-0: value\r\nHeader-1"));
+}
+
+#[test]
+fn buf_writer_flushes_when_full() {
+    let inner = CountingWriter {
+        writes: Vec::new(),
+        max_write: usize::MAX,
+    };
+    let mut w = BufWriter::with_capacity(4, inner);
+    w.write_all(b"abc").unwrap();
+    w.write_all(b"de").unwrap();
+    // Writes as big as the buffer skip it.
+    w.write_all(b"fghij").unwrap();
+    let inner = w.into_inner().unwrap();
+    assert_eq!(inner.writes, [&b"abc"[..], b"de", b"fghij"]);
+}
+
+#[test]
+fn buf_writer_partial_writes() {
+    let inner = CountingWriter {
+        writes: Vec::new(),
+        max_write: 2,
+    };
+    let mut w = BufWriter::new(inner);
+    w.write_all(b"hello").unwrap();
+    let inner = w.into_inner().unwrap();
+    assert_eq!(inner.writes, [&b"he"[..], b"ll", b"o"]);
+}
+
+#[test]
+fn write_to_vec() {
+    let mut v = Vec::new();
+    v.write_all(b"hello").unwrap();
+    v.write_all(b" world").unwrap();
+    v.flush().unwrap();
+    assert_eq!(v, b"hello world");
+}
+
+#[test]
+fn copy_to_vec() {
+    let mut v = b"existing ".to_vec();
+    let copied = io::copy(&mut &b"data"[..], &mut v).unwrap();
+    assert_eq!(copied, 4);
+    assert_eq!(v, b"existing data");
+
+    let mut read_back = Vec::new();
+    (&v[..]).read_to_end(&mut read_back).unwrap();
+    assert_eq!(read_back, v);
+}
+
+#[test]
+fn cursor_seek() {
+    let mut c = Cursor::new(b"hello world");
+    let mut buf = [0; 5];
+
+    assert_eq!(c.seek(SeekFrom::Start(6)).unwrap(), 6);
+    c.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    assert_eq!(c.seek(SeekFrom::End(-11)).unwrap(), 0);
+    c.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    assert_eq!(c.seek(SeekFrom::Current(1)).unwrap(), 6);
+    assert_eq!(c.seek(SeekFrom::Current(-6)).unwrap(), 0);
+    assert!(c.seek(SeekFrom::Current(-1)).is_err());
+    assert_eq!(c.position(), 0);
+
+    // Seeking past the end is allowed, there is just nothing to read.
+    assert_eq!(c.seek(SeekFrom::End(5)).unwrap(), 16);
+    assert_eq!(c.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn chain() {
+    let mut chained = (&b"hello "[..]).chain(&b"world"[..]);
+    let mut buf = Vec::new();
+    chained.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"hello world");
+
+    let mut empty_first = (&b""[..]).chain(&b"second"[..]);
+    let mut buf = Vec::new();
+    empty_first.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"second");
+}
+
+/// Hands out at most `max_read` bytes of `data` per read, and counts the reads.
+struct TrickleReader {
+    data: &'static [u8],
+    max_read: usize,
+    reads: usize,
+}
+
+impl Read for TrickleReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reads += 1;
+        let len = buf.len().min(self.max_read);
+        self.data.read(&mut buf[..len])
+    }
+}
+
+fn trickle(data: &'static [u8], max_read: usize) -> TrickleReader {
+    TrickleReader {
+        data,
+        max_read,
+        reads: 0,
+    }
+}
+
+#[test]
+fn read_until_across_refills() {
+    let mut reader = BufReader::with_capacity(4, trickle(b"GET / HTTP/1.1\r\nA: b\r\n\r\nrest", 3));
+    let mut lines = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        reader.read_until(b'\n', &mut line).unwrap();
+        if line == b"\r\n" {
+            break;
+        }
+        lines.push(line);
+    }
+    assert_eq!(lines, [&b"GET / HTTP/1.1\r\n"[..], b"A: b\r\n"]);
+
+    // What follows the delimiter is still there to be read.
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"rest");
+}
+
+#[test]
+fn read_until_end_of_stream() {
+    let mut reader = BufReader::with_capacity(2, trickle(b"no newline", 5));
+    let mut line = Vec::new();
+    assert_eq!(reader.read_until(b'\n', &mut line).unwrap(), 10);
+    assert_eq!(line, b"no newline");
+    line.clear();
+    assert_eq!(reader.read_until(b'\n', &mut line).unwrap(), 0);
+    assert!(line.is_empty());
+}
+
+#[test]
+fn read_line() {
+    let mut reader = BufReader::with_capacity(3, &b"first\nsecond\n\xff\n"[..]);
+    let mut line = String::new();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 6);
+    assert_eq!(reader.read_line(&mut line).unwrap(), 7);
+    assert_eq!(line, "first\nsecond\n");
+    assert!(reader.read_line(&mut line).is_err());
+    assert_eq!(line, "first\nsecond\n");
+}
+
+#[test]
+fn buf_reader_buffers() {
+    let mut reader = BufReader::with_capacity(16, trickle(b"0123456789abcdefghij", 100));
+    let mut buf = [0; 2];
+    for expected in [b"01", b"23", b"45"] {
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, expected);
+    }
+    assert_eq!(reader.get_ref().reads, 1);
+    assert_eq!(reader.buffer(), b"6789abcdef");
+
+    reader.consume(4);
+    assert_eq!(reader.fill_buf().unwrap(), b"abcdef");
+
+    // Reads at least as large as the buffer skip it once it is empty.
+    reader.consume(6);
+    let mut large = [0; 16];
+    assert_eq!(reader.read(&mut large).unwrap(), 4);
+    assert_eq!(&large[..4], b"ghij");
+    assert_eq!(reader.get_ref().reads, 2);
+}